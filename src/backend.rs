@@ -1,12 +1,18 @@
 use std::{
     collections::HashMap,
     io::Write,
-    process::{Command, Stdio},
+    path::PathBuf,
+    process::{Command, Output, Stdio},
 };
 
 use serde_json::{self, Map, Value};
 
-use crate::types::{ActionResultKey, ActionValue, JujuCredentials, LogLevel, Status};
+use crate::model::{GoalState, ModelInfo};
+use crate::schema;
+use crate::types::{
+    ActionResultKey, ActionValue, Base, JujuCredentials, JujuFeature, JujuVersion, LogLevel,
+    MetricDefinition, OpenedPort, Payload, PayloadStatus, Port, Status,
+};
 use crate::{
     error::{Error, Result},
     types::{RelatedApp, RelatedUnit},
@@ -19,17 +25,37 @@ pub trait Backend {
     fn leader_get(&self) -> Result<HashMap<String, String>>;
     fn leader_set(&self, key: &str, value: &str) -> Result<()>;
     fn credentials(&self) -> Result<JujuCredentials>;
+    /// Request a reboot of the machine, after the current hook (or immediately if `now`).
+    /// Returns `Error::RebootInAction` if called while running an action,
+    /// which juju does not allow.
     fn reboot(&self, now: bool) -> Result<()>;
+    /// Get an ID that is unique to the current boot of the machine.
+    fn boot_id(&self) -> Result<String>;
     fn set_application_version(&self, version: &str) -> Result<()>;
+    /// Get the metrics defined in the charm's `metrics.yaml`, keyed by name.
+    /// Returns an empty map if the charm doesn't define any metrics.
+    fn metric_definitions(&self) -> Result<HashMap<String, MetricDefinition>>;
+    /// Record a metric value, with optional labels.
+    /// Only call this during the collect-metrics hook.
+    fn add_metric(&self, name: &str, value: f64, labels: &HashMap<String, String>) -> Result<()>;
     fn set_action_fail(&self, msg: &str) -> Result<()>;
     fn set_action_result(&self, data: HashMap<ActionResultKey, ActionValue>) -> Result<()>;
     fn action_name(&self) -> Result<String>;
+    /// Get the unique ID of the currently running action.
+    fn action_id(&self) -> Result<String>;
     fn hook_name(&self) -> Result<String>;
+    /// Get the name of the unit the charm code is running on (eg. `rusty/0`).
+    fn unit_name(&self) -> Result<String>;
+    /// Get the identity of this unit and the model it is deployed in.
+    fn model_info(&self) -> Result<ModelInfo>;
     /// Log a message to the juju log, at the desired log level.
     fn log(&self, msg: &str, level: LogLevel) -> Result<()>;
-    fn action<A>(&self) -> Result<A>
-    where
-        A: serde::de::DeserializeOwned;
+    /// Retrieve the raw parameters for the current action.
+    fn action_params(&self) -> Result<Value>;
+    /// Retrieve the JSON-schema for the parameters of the named action,
+    /// as defined in the charm's `actions.yaml`.
+    /// Returns `None` if there is no schema available for the action.
+    fn action_schema(&self, name: &str) -> Result<Option<Value>>;
     /// Retrieve the charm's current config as something that can be deserialised.
     fn config<C>(&self) -> Result<C>
     where
@@ -42,23 +68,63 @@ pub trait Backend {
     /// action handler function).
     fn action_log(&self, msg: &str) -> Result<()>;
     fn is_leader(&self) -> Result<bool>;
-    fn opened_ports(&self) -> Result<Vec<String>>;
-    fn open_port(&self, port: &str, endpoints: Vec<&str>) -> Result<()>;
-    fn close_port(&self, port: &str, endpoints: Vec<&str>) -> Result<()>;
+    /// Register a payload with juju, so it is reported in `juju payloads`.
+    fn payload_register(&self, payload: &Payload) -> Result<()>;
+    /// Stop tracking the payload of `class` with `id`.
+    fn payload_unregister(&self, class: &str, id: &str) -> Result<()>;
+    /// Update the status of the payload of `class` with `id`.
+    fn payload_status_set(&self, class: &str, id: &str, status: PayloadStatus) -> Result<()>;
+    /// Get the units and relations juju expects this application to have.
+    fn goal_state(&self) -> Result<GoalState>;
+    /// Get the ports opened by this unit, including the endpoints they are opened for.
+    fn opened_ports(&self) -> Result<Vec<OpenedPort>>;
+    /// Open a port for the given endpoints, or all endpoints if `endpoints` is empty.
+    fn open_port(&self, port: &Port, endpoints: Vec<&str>) -> Result<()>;
+    /// Close a port for the given endpoints, or all endpoints if `endpoints` is empty.
+    fn close_port(&self, port: &Port, endpoints: Vec<&str>) -> Result<()>;
     fn get_unit_state(&self) -> Result<HashMap<String, String>>;
     fn set_unit_state(&self, key: &str, value: &str) -> Result<()>;
     fn delete_unit_state(&self, key: &str) -> Result<()>;
-    fn resource_path(&self, name: &str) -> Result<String>;
+    /// Get the path to the file for the resource `name`, downloading it if necessary.
+    /// Returns `Error::ResourceMissing` if the resource has not been uploaded.
+    fn resource_path(&self, name: &str) -> Result<PathBuf>;
+
+    /// Get the base the machine is being upgraded to,
+    /// during the pre-series-upgrade and post-series-upgrade hooks.
+    /// Returns `None` if juju has not set a target.
+    fn target_base(&self) -> Result<Option<Base>>;
+
+    /// Get the content of the secret identified by `uri`.
+    /// Returns `Error::SecretPermissionDenied` if the charm has not been granted access.
+    fn secret_get(&self, uri: &str) -> Result<HashMap<String, String>>;
 
     /// Get all apps related on the given `endpoint`.
     fn related_apps(&self, endpoint: &str) -> Result<Vec<RelatedApp>>;
 
+    /// Get the app related by the relation with id `relation_id` (eg. `database:3`).
+    fn related_app(&self, relation_id: &str) -> Result<RelatedApp>;
+
+    /// Get the id of the relation the current relation event is for.
+    /// Returns `None` outside of relation events.
+    fn relation_id(&self) -> Result<Option<String>>;
+
+    /// Get the UUID of the model the related app is deployed in.
+    /// This is the local model's UUID, except for cross-model relations.
+    fn relation_model_uuid(&self, app: &RelatedApp) -> Result<String>;
+
     /// Get relation data for the remote related application.
     fn relation_get_app(&self, app: &RelatedApp) -> Result<HashMap<String, String>>;
 
     /// Get relation data for the remote related unit.
     fn relation_get_unit(&self, unit: &RelatedUnit) -> Result<HashMap<String, String>>;
 
+    /// Get the relation data this application has set on the relation with `app`.
+    /// Except for peer relations, only the leader can read this.
+    fn relation_get_local_app(&self, app: &RelatedApp) -> Result<HashMap<String, String>>;
+
+    /// Get the relation data this unit has set on the relation with `app`.
+    fn relation_get_local_unit(&self, app: &RelatedApp) -> Result<HashMap<String, String>>;
+
     /// Set data on the relation on behalf of this unit.
     /// These key/values can be read by the related application,
     /// when the related application calls relation-get with this unit's name.
@@ -69,36 +135,96 @@ pub trait Backend {
     /// These key/values can be read by the related application,
     /// when the related application calls relation-get with `--app`.
     fn relation_set_app(&self, app: &RelatedApp, key: &str, value: &str) -> Result<()>;
+
+    /// Set several keys on the relation in one call,
+    /// on behalf of this application if `on_app` is true (leader only), or this unit otherwise.
+    /// Keys with a `None` value are deleted.
+    fn relation_set_many(
+        &self,
+        app: &RelatedApp,
+        data: &HashMap<String, Option<String>>,
+        on_app: bool,
+    ) -> Result<()>;
 }
 
 /// The real implementation for the backend.
 pub struct JujuBackend {}
 
+/// The version of the running juju agent, if known.
+fn juju_version() -> Option<JujuVersion> {
+    std::env::var("JUJU_VERSION").ok()?.parse().ok()
+}
+
+/// Run a hook tool, returning an error including stderr if it exits unsuccessfully.
+fn run(tool: &str, args: &[&str]) -> Result<Output> {
+    check_status(tool, Command::new(tool).args(args).output()?)
+}
+
+/// Run a hook tool with `input` written to its stdin,
+/// returning an error including stderr if it exits unsuccessfully.
+fn run_with_input(tool: &str, args: &[&str], input: &[u8]) -> Result<Output> {
+    let mut child = Command::new(tool)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    {
+        // stdin must be closed before waiting, so the tool sees the end of the input
+        let mut stdin = child.stdin.take().ok_or(Error::StdinError())?;
+        stdin.write_all(input)?;
+    }
+    check_status(tool, child.wait_with_output()?)
+}
+
+fn check_status(tool: &str, output: Output) -> Result<Output> {
+    if !output.status.success() {
+        return Err(Error::HookToolError {
+            tool: tool.to_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        });
+    }
+    Ok(output)
+}
+
 impl JujuBackend {
-    fn relation_set(&self, app: &RelatedApp, key: &str, value: &str, on_app: bool) -> Result<()> {
-        let mut args = vec!["--file", "-", "--relation", &app.relation_id];
-        if on_app {
+    /// Return `Error::UnsupportedByJuju` if the running juju agent is too old for `feature`.
+    /// If the version can't be determined, assume the feature is supported.
+    fn require(&self, feature: JujuFeature) -> Result<()> {
+        match juju_version() {
+            Some(version) if !version.supports(feature) => Err(Error::UnsupportedByJuju {
+                feature,
+                required: feature.required_version(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Read the relation data written by `name` (a unit, or an application if `app` is true).
+    fn relation_get(
+        &self,
+        relation_id: &str,
+        name: &str,
+        app: bool,
+    ) -> Result<HashMap<String, String>> {
+        let mut args = vec!["--format", "json", "--relation", relation_id];
+        if app {
             args.push("--app");
         }
-        let process = Command::new("relation-set")
-            .args(args)
-            .stdin(Stdio::piped())
-            .spawn()?;
+        args.extend(["-", name]);
+        let output = run("relation-get", &args)?;
+        Ok(serde_json::from_slice(&output.stdout)?)
+    }
 
-        let json_data = Value::Object({
-            let mut map = Map::new();
-            map.insert(key.to_owned(), Value::String(value.to_owned()));
-            map
-        });
-        let data = serde_json::to_vec(&json_data)?;
-        let mut stdin = process.stdin.ok_or(Error::StdinError())?;
-        stdin.write_all(&data)?;
-        Ok(())
+    fn relation_set(&self, app: &RelatedApp, key: &str, value: &str, on_app: bool) -> Result<()> {
+        let data = HashMap::from([(key.to_owned(), Some(value.to_owned()))]);
+        self.relation_set_many(app, &data, on_app)
     }
 }
 
 impl Backend for JujuBackend {
     fn action_log(&self, msg: &str) -> Result<()> {
+        self.require(JujuFeature::ActionLog)?;
         Command::new("action-log").args([msg]).output()?;
         Ok(())
     }
@@ -134,22 +260,20 @@ impl Backend for JujuBackend {
         Ok(())
     }
 
-    fn action<A>(&self) -> Result<A>
-    where
-        A: serde::de::DeserializeOwned,
-    {
-        let name = self.action_name()?;
+    fn action_params(&self) -> Result<Value> {
         let output = Command::new("action-get")
             .args(["--format", "json"])
             .output()?;
-        let params = serde_json::from_slice::<Value>(&output.stdout)?;
+        Ok(serde_json::from_slice::<Value>(&output.stdout)?)
+    }
 
-        let action_value = Value::Object({
-            let mut map = Map::new();
-            map.insert(name.to_owned(), params);
-            map
-        });
-        Ok(serde_json::from_value(action_value)?)
+    fn action_schema(&self, name: &str) -> Result<Option<Value>> {
+        let path = std::path::Path::new(&std::env::var("JUJU_CHARM_DIR")?).join("actions.yaml");
+        if !path.exists() {
+            return Ok(None);
+        }
+        let actions: Value = serde_yaml::from_str(&std::fs::read_to_string(path)?)?;
+        Ok(schema::action_schema(&actions, name))
     }
 
     fn hook_name(&self) -> Result<String> {
@@ -160,6 +284,30 @@ impl Backend for JujuBackend {
         Ok(std::env::var("JUJU_ACTION_NAME")?)
     }
 
+    fn action_id(&self) -> Result<String> {
+        Ok(std::env::var("JUJU_ACTION_UUID")?)
+    }
+
+    fn unit_name(&self) -> Result<String> {
+        Ok(std::env::var("JUJU_UNIT_NAME")?)
+    }
+
+    fn model_info(&self) -> Result<ModelInfo> {
+        let optional = |name| std::env::var(name).ok().filter(|value| !value.is_empty());
+        let unit_name = self.unit_name()?;
+        Ok(ModelInfo {
+            app_name: unit_name.split('/').next().unwrap_or_default().to_owned(),
+            unit_name,
+            principal_unit: optional("JUJU_PRINCIPAL_UNIT"),
+            model_name: std::env::var("JUJU_MODEL_NAME")?,
+            model_uuid: std::env::var("JUJU_MODEL_UUID")?,
+            machine_id: optional("JUJU_MACHINE_ID"),
+            availability_zone: optional("JUJU_AVAILABILITY_ZONE"),
+            charm_dir: std::env::var("JUJU_CHARM_DIR")?.into(),
+            juju_version: juju_version(),
+        })
+    }
+
     fn set_action_result(&self, data: HashMap<ActionResultKey, ActionValue>) -> Result<()> {
         if data.is_empty() {
             return Ok(());
@@ -182,6 +330,60 @@ impl Backend for JujuBackend {
         Ok(())
     }
 
+    fn metric_definitions(&self) -> Result<HashMap<String, MetricDefinition>> {
+        #[derive(serde::Deserialize)]
+        struct Metrics {
+            #[serde(default)]
+            metrics: HashMap<String, MetricDefinition>,
+        }
+
+        let path = std::path::Path::new(&std::env::var("JUJU_CHARM_DIR")?).join("metrics.yaml");
+        if !path.exists() {
+            return Ok(HashMap::new());
+        }
+        Ok(serde_yaml::from_str::<Metrics>(&std::fs::read_to_string(path)?)?.metrics)
+    }
+
+    fn add_metric(&self, name: &str, value: f64, labels: &HashMap<String, String>) -> Result<()> {
+        let mut labels: Vec<String> = labels
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect();
+        // sorted for consistent output
+        labels.sort();
+        let labels = labels.join(",");
+        let metric = format!("{name}={value}");
+
+        let mut args = vec![];
+        if !labels.is_empty() {
+            args.extend(["--labels", &labels]);
+        }
+        args.push(&metric);
+        run("add-metric", &args)?;
+        Ok(())
+    }
+
+    fn payload_register(&self, payload: &Payload) -> Result<()> {
+        let mut args = vec![
+            payload.kind.as_str(),
+            payload.class.as_str(),
+            payload.id.as_str(),
+        ];
+        args.extend(payload.labels.iter().map(String::as_str));
+        run("payload-register", &args)?;
+        Ok(())
+    }
+
+    fn payload_unregister(&self, class: &str, id: &str) -> Result<()> {
+        run("payload-unregister", &[class, id])?;
+        Ok(())
+    }
+
+    fn payload_status_set(&self, class: &str, id: &str, status: PayloadStatus) -> Result<()> {
+        run("payload-status-set", &[class, id, &status.to_string()])?;
+        Ok(())
+    }
+
     fn is_leader(&self) -> Result<bool> {
         let output = Command::new("is-leader")
             .args(["--format", "json"])
@@ -189,40 +391,57 @@ impl Backend for JujuBackend {
         Ok(serde_json::from_slice::<bool>(&output.stdout)?)
     }
 
-    fn opened_ports(&self) -> Result<Vec<String>> {
-        let output = Command::new("opened-ports")
-            .args(["--format", "json"])
-            .output()?;
-        Ok(serde_json::from_slice::<Vec<String>>(&output.stdout)?)
+    fn goal_state(&self) -> Result<GoalState> {
+        let output = run("goal-state", &["--format", "json"])?;
+        Ok(serde_json::from_slice(&output.stdout)?)
+    }
+
+    fn opened_ports(&self) -> Result<Vec<OpenedPort>> {
+        // Older juju versions can only open ports for all endpoints,
+        // so the ports are still correct without the endpoints.
+        let output = if self.require(JujuFeature::OpenPortEndpoints).is_ok() {
+            run("opened-ports", &["--format", "json", "--endpoints"])?
+        } else {
+            run("opened-ports", &["--format", "json"])?
+        };
+        serde_json::from_slice::<Vec<String>>(&output.stdout)?
+            .iter()
+            .map(|line| line.parse())
+            .collect()
     }
 
-    fn open_port(&self, port: &str, endpoints: Vec<&str>) -> Result<()> {
+    fn open_port(&self, port: &Port, endpoints: Vec<&str>) -> Result<()> {
         let mut args = vec![];
         let endpoints = endpoints.join(",");
         if !endpoints.is_empty() {
+            self.require(JujuFeature::OpenPortEndpoints)?;
             args.push("--endpoints");
             args.push(&endpoints);
         }
-        args.push(port);
+        let port = port.to_string();
+        args.push(&port);
 
-        Command::new("open-port").args(&args).output()?;
+        run("open-port", &args)?;
         Ok(())
     }
 
-    fn close_port(&self, port: &str, endpoints: Vec<&str>) -> Result<()> {
+    fn close_port(&self, port: &Port, endpoints: Vec<&str>) -> Result<()> {
         let mut args = vec![];
         let endpoints = endpoints.join(",");
         if !endpoints.is_empty() {
+            self.require(JujuFeature::OpenPortEndpoints)?;
             args.push("--endpoints");
             args.push(&endpoints);
         }
-        args.push(port);
+        let port = port.to_string();
+        args.push(&port);
 
-        Command::new("close-port").args(&args).output()?;
+        run("close-port", &args)?;
         Ok(())
     }
 
     fn get_unit_state(&self) -> Result<HashMap<String, String>> {
+        self.require(JujuFeature::UnitState)?;
         let output = Command::new("state-get")
             .args(["--format", "json"])
             .output()?;
@@ -232,38 +451,90 @@ impl Backend for JujuBackend {
     // NOTE: setting the unit state will not reflect in the state returned from state-get
     // until the next hook invocation.
     fn set_unit_state(&self, key: &str, value: &str) -> Result<()> {
-        let process = Command::new("state-set")
-            .args(["--file", "-"])
-            .stdin(Stdio::piped())
-            .spawn()?;
-
+        self.require(JujuFeature::UnitState)?;
         let json_data = Value::Object({
             let mut map = Map::new();
             map.insert(key.to_owned(), Value::String(value.to_owned()));
             map
         });
-        let data = serde_json::to_vec(&json_data)?;
-        let mut stdin = process.stdin.ok_or(Error::StdinError())?;
-        stdin.write_all(&data)?;
+        run_with_input(
+            "state-set",
+            &["--file", "-"],
+            &serde_json::to_vec(&json_data)?,
+        )?;
         Ok(())
     }
 
     fn delete_unit_state(&self, key: &str) -> Result<()> {
+        self.require(JujuFeature::UnitState)?;
         Command::new("state-delete").args([key]).output()?;
         Ok(())
     }
 
-    fn resource_path(&self, name: &str) -> Result<String> {
-        let output = Command::new("resource-get").args([name]).output()?;
-        Ok(String::from_utf8(output.stdout)?)
+    fn resource_path(&self, name: &str) -> Result<PathBuf> {
+        let output = match run("resource-get", &[name]) {
+            Ok(output) => output,
+            Err(Error::HookToolError { stderr, .. })
+                if stderr.contains("not found") || stderr.contains("could not download") =>
+            {
+                return Err(Error::ResourceMissing(name.to_owned()));
+            }
+            Err(e) => return Err(e),
+        };
+        let path = String::from_utf8(output.stdout)?;
+        let path = path.trim();
+        if path.is_empty() {
+            return Err(Error::ResourceMissing(name.to_owned()));
+        }
+        Ok(PathBuf::from(path))
+    }
+
+    fn target_base(&self) -> Result<Option<Base>> {
+        // juju 3 sets the base, while juju 2.9 sets the series
+        if let Some(base) = std::env::var("JUJU_TARGET_BASE")
+            .ok()
+            .filter(|base| !base.is_empty())
+        {
+            return Ok(Some(base.parse()?));
+        }
+        match std::env::var("JUJU_TARGET_SERIES")
+            .ok()
+            .filter(|series| !series.is_empty())
+        {
+            Some(series) => Ok(Some(Base::from_series(&series)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn secret_get(&self, uri: &str) -> Result<HashMap<String, String>> {
+        self.require(JujuFeature::Secrets)?;
+        match run("secret-get", &["--format", "json", uri]) {
+            Ok(output) => Ok(serde_json::from_slice(&output.stdout)?),
+            Err(Error::HookToolError { stderr, .. }) if stderr.contains("permission denied") => {
+                Err(Error::SecretPermissionDenied(uri.to_owned()))
+            }
+            Err(Error::HookToolError { stderr, .. }) if stderr.contains("not found") => {
+                Err(Error::SecretNotFound(uri.to_owned()))
+            }
+            Err(e) => Err(e),
+        }
     }
 
     fn reboot(&self, now: bool) -> Result<()> {
+        if !self.action_name().unwrap_or_default().is_empty() {
+            return Err(Error::RebootInAction);
+        }
         let args = if now { vec!["--now"] } else { vec![] };
         Command::new("juju-reboot").args(&args).output()?;
         Ok(())
     }
 
+    fn boot_id(&self) -> Result<String> {
+        Ok(std::fs::read_to_string("/proc/sys/kernel/random/boot_id")?
+            .trim()
+            .to_owned())
+    }
+
     fn credentials(&self) -> Result<JujuCredentials> {
         let output = Command::new("credential-get")
             .args(["--format", "json"])
@@ -292,71 +563,82 @@ impl Backend for JujuBackend {
             .output()?;
         let relation_ids: Vec<String> = serde_json::from_slice(&relation_ids_output.stdout)?;
 
-        let mut apps: Vec<RelatedApp> = vec![];
-        for relation_id in relation_ids {
-            let relation_list_output_app = Command::new("relation-list")
-                .args([
-                    "--format",
-                    "json",
-                    "--relation",
-                    relation_id.as_str(),
-                    "--app",
-                ])
-                .output()?;
-            let relation_list_output_units = Command::new("relation-list")
-                .args(["--format", "json", "--relation", relation_id.as_str()])
-                .output()?;
-
-            let app_name: String = serde_json::from_slice(&relation_list_output_app.stdout)?;
-            let unit_names: Vec<String> =
-                serde_json::from_slice(&relation_list_output_units.stdout)?;
-
-            apps.push(RelatedApp {
-                endpoint: endpoint.to_string(),
-                relation_id: relation_id.clone(),
-                name: app_name.clone(),
-                units: unit_names
-                    .into_iter()
-                    .map(|name| RelatedUnit {
-                        name,
-                        endpoint: endpoint.to_string(),
-                        relation_id: relation_id.clone(),
-                        app_name: app_name.clone(),
-                    })
-                    .collect(),
-            });
+        relation_ids
+            .iter()
+            .map(|relation_id| self.related_app(relation_id))
+            .collect()
+    }
+
+    fn related_app(&self, relation_id: &str) -> Result<RelatedApp> {
+        // relation ids are in the form <endpoint>:<number>
+        let endpoint = match relation_id.split_once(':') {
+            Some((endpoint, number)) if number.parse::<u64>().is_ok() => endpoint,
+            _ => return Err(Error::InvalidRelationId(relation_id.to_owned())),
+        };
+
+        let relation_list_output_app = run(
+            "relation-list",
+            &["--format", "json", "--relation", relation_id, "--app"],
+        )?;
+        let relation_list_output_units = run(
+            "relation-list",
+            &["--format", "json", "--relation", relation_id],
+        )?;
+
+        let app_name: String = serde_json::from_slice(&relation_list_output_app.stdout)?;
+        let unit_names: Vec<String> = serde_json::from_slice(&relation_list_output_units.stdout)?;
+
+        Ok(RelatedApp {
+            endpoint: endpoint.to_string(),
+            relation_id: relation_id.to_owned(),
+            name: app_name.clone(),
+            units: unit_names
+                .into_iter()
+                .map(|name| RelatedUnit {
+                    name,
+                    endpoint: endpoint.to_string(),
+                    relation_id: relation_id.to_owned(),
+                    app_name: app_name.clone(),
+                })
+                .collect(),
+        })
+    }
+
+    fn relation_id(&self) -> Result<Option<String>> {
+        Ok(std::env::var("JUJU_RELATION_ID")
+            .ok()
+            .filter(|relation_id| !relation_id.is_empty()))
+    }
+
+    fn relation_model_uuid(&self, app: &RelatedApp) -> Result<String> {
+        #[derive(serde::Deserialize)]
+        struct RelationModel {
+            uuid: String,
         }
 
-        Ok(apps)
+        let output = run(
+            "relation-model-get",
+            &["--format", "json", "--relation", &app.relation_id],
+        )?;
+        Ok(serde_json::from_slice::<RelationModel>(&output.stdout)?.uuid)
     }
 
     fn relation_get_app(&self, app: &RelatedApp) -> Result<HashMap<String, String>> {
-        let output = Command::new("relation-get")
-            .args([
-                "--format",
-                "json",
-                "--app",
-                "--relation",
-                &app.relation_id,
-                "-",
-                &app.name,
-            ])
-            .output()?;
-        Ok(serde_json::from_slice(&output.stdout)?)
+        self.relation_get(&app.relation_id, &app.name, true)
     }
 
     fn relation_get_unit(&self, unit: &RelatedUnit) -> Result<HashMap<String, String>> {
-        let output = Command::new("relation-get")
-            .args([
-                "--format",
-                "json",
-                "--relation",
-                &unit.relation_id,
-                "-",
-                &unit.name,
-            ])
-            .output()?;
-        Ok(serde_json::from_slice(&output.stdout)?)
+        self.relation_get(&unit.relation_id, &unit.name, false)
+    }
+
+    fn relation_get_local_app(&self, app: &RelatedApp) -> Result<HashMap<String, String>> {
+        let unit_name = self.unit_name()?;
+        let app_name = unit_name.split('/').next().unwrap_or_default();
+        self.relation_get(&app.relation_id, app_name, true)
+    }
+
+    fn relation_get_local_unit(&self, app: &RelatedApp) -> Result<HashMap<String, String>> {
+        self.relation_get(&app.relation_id, &self.unit_name()?, false)
     }
 
     fn relation_set_unit(&self, app: &RelatedApp, key: &str, value: &str) -> Result<()> {
@@ -366,6 +648,34 @@ impl Backend for JujuBackend {
     fn relation_set_app(&self, app: &RelatedApp, key: &str, value: &str) -> Result<()> {
         self.relation_set(app, key, value, true)
     }
+
+    fn relation_set_many(
+        &self,
+        app: &RelatedApp,
+        data: &HashMap<String, Option<String>>,
+        on_app: bool,
+    ) -> Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        let mut args = vec!["--file", "-", "--relation", &app.relation_id];
+        if on_app {
+            args.push("--app");
+        }
+        // relation-set deletes keys that are set to an empty string
+        let json_data = Value::Object(
+            data.iter()
+                .map(|(key, value)| {
+                    (
+                        key.to_owned(),
+                        Value::String(value.clone().unwrap_or_default()),
+                    )
+                })
+                .collect(),
+        );
+        run_with_input("relation-set", &args, &serde_json::to_vec(&json_data)?)?;
+        Ok(())
+    }
 }
 
 // Convert a custom nested hashmap into path.to.key=value notation for juju action-set.
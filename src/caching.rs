@@ -0,0 +1,504 @@
+// A backend wrapper that avoids spawning the same hook tool more than once per dispatch.
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    path::PathBuf,
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use serde_json::Value;
+
+use crate::backend::Backend;
+use crate::error::Result;
+use crate::model::{GoalState, ModelInfo};
+use crate::types::{
+    ActionResultKey, ActionValue, Base, JujuCredentials, LogLevel, MetricDefinition, OpenedPort,
+    Payload, PayloadStatus, Port, RelatedApp, RelatedUnit, Status,
+};
+
+/// Wraps a backend to remember the results of read calls,
+/// so a handler can call eg. `is_leader` or read relation data many times
+/// while only running each hook tool once.
+/// Writes through this backend invalidate the cached results they affect;
+/// for example `relation_set_unit` invalidates this unit's data for that relation.
+///
+/// The cache lives as long as the backend, which is one hook or action dispatch when used with
+/// `Framework`.
+/// Changes made by other units during the dispatch are not seen,
+/// which matches juju's own view of relation data during a hook.
+///
+/// ```ignore
+/// Framework::new(CachingBackend::new(JujuBackend {}), event_handler, action_handler)
+///     .execute()
+/// ```
+pub struct CachingBackend<B> {
+    inner: B,
+    cache: Mutex<Cache>,
+}
+
+/// Identifies a relation databag within a relation, by the name of the app or unit that owns it.
+/// Keying by name rather than by which side of the relation is reading means that on peer
+/// relations, where the remote app is the local app, both views share one cache entry.
+#[derive(PartialEq, Eq, Hash)]
+enum Databag {
+    App(String),
+    Unit(String),
+}
+
+#[derive(Default)]
+struct Cache {
+    leader_data: Option<HashMap<String, String>>,
+    is_leader: Option<bool>,
+    credentials: Option<JujuCredentials>,
+    config: Option<Value>,
+    unit_state: Option<HashMap<String, String>>,
+    goal_state: Option<GoalState>,
+    opened_ports: Option<Vec<OpenedPort>>,
+    metric_definitions: Option<HashMap<String, MetricDefinition>>,
+    /// Related apps, keyed by endpoint.
+    related_apps: HashMap<String, Vec<RelatedApp>>,
+    /// Related apps, keyed by relation id.
+    related_app: HashMap<String, RelatedApp>,
+    /// Model UUIDs of related apps, keyed by relation id.
+    relation_model_uuids: HashMap<String, String>,
+    /// Relation data, keyed by relation id and databag.
+    databags: HashMap<(String, Databag), HashMap<String, String>>,
+    /// Secret content, keyed by URI.
+    secrets: HashMap<String, HashMap<String, String>>,
+}
+
+impl<B> CachingBackend<B>
+where
+    B: Backend,
+{
+    pub fn new(inner: B) -> Self {
+        Self {
+            inner,
+            cache: Mutex::new(Cache::default()),
+        }
+    }
+
+    /// Forget all cached results, so the next read of each calls the hook tool again.
+    pub fn invalidate_all(&self) {
+        *self.cache() = Cache::default();
+    }
+
+    fn cache(&self) -> MutexGuard<'_, Cache> {
+        // Each update to the cache is a single assignment, so it's still consistent after a panic.
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Return the cached value in `slot`, or load and cache it.
+    /// The lock isn't held while loading, so loads can't deadlock on the cache.
+    fn cached<T: Clone>(
+        &self,
+        slot: fn(&mut Cache) -> &mut Option<T>,
+        load: impl FnOnce() -> Result<T>,
+    ) -> Result<T> {
+        if let Some(value) = slot(&mut self.cache()) {
+            return Ok(value.clone());
+        }
+        let value = load()?;
+        *slot(&mut self.cache()) = Some(value.clone());
+        Ok(value)
+    }
+
+    /// Return the value cached for `key` in `map`, or load and cache it.
+    fn cached_by<K: Eq + Hash, T: Clone>(
+        &self,
+        map: fn(&mut Cache) -> &mut HashMap<K, T>,
+        key: K,
+        load: impl FnOnce() -> Result<T>,
+    ) -> Result<T> {
+        if let Some(value) = map(&mut self.cache()).get(&key) {
+            return Ok(value.clone());
+        }
+        let value = load()?;
+        map(&mut self.cache()).insert(key, value.clone());
+        Ok(value)
+    }
+
+    /// The databag of this application.
+    fn local_app(&self) -> Result<Databag> {
+        let unit_name = self.inner.unit_name()?;
+        let app_name = unit_name
+            .split_once('/')
+            .map_or(&*unit_name, |(app, _)| app);
+        Ok(Databag::App(app_name.to_owned()))
+    }
+
+    /// The databag of this unit.
+    fn local_unit(&self) -> Result<Databag> {
+        Ok(Databag::Unit(self.inner.unit_name()?))
+    }
+
+    fn invalidate_databag(&self, app: &RelatedApp, databag: Databag) {
+        self.cache()
+            .databags
+            .remove(&(app.relation_id().to_owned(), databag));
+    }
+}
+
+impl<B> Backend for CachingBackend<B>
+where
+    B: Backend,
+{
+    fn leader_get(&self) -> Result<HashMap<String, String>> {
+        self.cached(|cache| &mut cache.leader_data, || self.inner.leader_get())
+    }
+
+    fn leader_set(&self, key: &str, value: &str) -> Result<()> {
+        self.cache().leader_data = None;
+        self.inner.leader_set(key, value)
+    }
+
+    fn credentials(&self) -> Result<JujuCredentials> {
+        self.cached(|cache| &mut cache.credentials, || self.inner.credentials())
+    }
+
+    fn reboot(&self, now: bool) -> Result<()> {
+        self.inner.reboot(now)
+    }
+
+    fn boot_id(&self) -> Result<String> {
+        self.inner.boot_id()
+    }
+
+    fn set_application_version(&self, version: &str) -> Result<()> {
+        self.inner.set_application_version(version)
+    }
+
+    fn metric_definitions(&self) -> Result<HashMap<String, MetricDefinition>> {
+        self.cached(
+            |cache| &mut cache.metric_definitions,
+            || self.inner.metric_definitions(),
+        )
+    }
+
+    fn add_metric(&self, name: &str, value: f64, labels: &HashMap<String, String>) -> Result<()> {
+        self.inner.add_metric(name, value, labels)
+    }
+
+    fn set_action_fail(&self, msg: &str) -> Result<()> {
+        self.inner.set_action_fail(msg)
+    }
+
+    fn set_action_result(&self, data: HashMap<ActionResultKey, ActionValue>) -> Result<()> {
+        self.inner.set_action_result(data)
+    }
+
+    fn action_name(&self) -> Result<String> {
+        self.inner.action_name()
+    }
+
+    fn action_id(&self) -> Result<String> {
+        self.inner.action_id()
+    }
+
+    fn hook_name(&self) -> Result<String> {
+        self.inner.hook_name()
+    }
+
+    fn unit_name(&self) -> Result<String> {
+        self.inner.unit_name()
+    }
+
+    fn model_info(&self) -> Result<ModelInfo> {
+        self.inner.model_info()
+    }
+
+    fn log(&self, msg: &str, level: LogLevel) -> Result<()> {
+        self.inner.log(msg, level)
+    }
+
+    fn action_params(&self) -> Result<Value> {
+        self.inner.action_params()
+    }
+
+    fn action_schema(&self, name: &str) -> Result<Option<Value>> {
+        self.inner.action_schema(name)
+    }
+
+    fn config<C>(&self) -> Result<C>
+    where
+        C: serde::de::DeserializeOwned,
+    {
+        let config = self.cached(|cache| &mut cache.config, || self.inner.config())?;
+        Ok(serde_json::from_value(config)?)
+    }
+
+    fn set_status(&self, status: Status) -> Result<()> {
+        self.inner.set_status(status)
+    }
+
+    fn set_app_status(&self, status: Status) -> Result<()> {
+        self.inner.set_app_status(status)
+    }
+
+    fn action_log(&self, msg: &str) -> Result<()> {
+        self.inner.action_log(msg)
+    }
+
+    fn is_leader(&self) -> Result<bool> {
+        self.cached(|cache| &mut cache.is_leader, || self.inner.is_leader())
+    }
+
+    fn payload_register(&self, payload: &Payload) -> Result<()> {
+        self.inner.payload_register(payload)
+    }
+
+    fn payload_unregister(&self, class: &str, id: &str) -> Result<()> {
+        self.inner.payload_unregister(class, id)
+    }
+
+    fn payload_status_set(&self, class: &str, id: &str, status: PayloadStatus) -> Result<()> {
+        self.inner.payload_status_set(class, id, status)
+    }
+
+    fn goal_state(&self) -> Result<GoalState> {
+        self.cached(|cache| &mut cache.goal_state, || self.inner.goal_state())
+    }
+
+    fn opened_ports(&self) -> Result<Vec<OpenedPort>> {
+        self.cached(
+            |cache| &mut cache.opened_ports,
+            || self.inner.opened_ports(),
+        )
+    }
+
+    fn open_port(&self, port: &Port, endpoints: Vec<&str>) -> Result<()> {
+        self.cache().opened_ports = None;
+        self.inner.open_port(port, endpoints)
+    }
+
+    fn close_port(&self, port: &Port, endpoints: Vec<&str>) -> Result<()> {
+        self.cache().opened_ports = None;
+        self.inner.close_port(port, endpoints)
+    }
+
+    fn get_unit_state(&self) -> Result<HashMap<String, String>> {
+        self.cached(
+            |cache| &mut cache.unit_state,
+            || self.inner.get_unit_state(),
+        )
+    }
+
+    fn set_unit_state(&self, key: &str, value: &str) -> Result<()> {
+        self.cache().unit_state = None;
+        self.inner.set_unit_state(key, value)
+    }
+
+    fn delete_unit_state(&self, key: &str) -> Result<()> {
+        self.cache().unit_state = None;
+        self.inner.delete_unit_state(key)
+    }
+
+    fn resource_path(&self, name: &str) -> Result<PathBuf> {
+        self.inner.resource_path(name)
+    }
+
+    fn target_base(&self) -> Result<Option<Base>> {
+        self.inner.target_base()
+    }
+
+    fn secret_get(&self, uri: &str) -> Result<HashMap<String, String>> {
+        self.cached_by(
+            |cache| &mut cache.secrets,
+            uri.to_owned(),
+            || self.inner.secret_get(uri),
+        )
+    }
+
+    fn related_apps(&self, endpoint: &str) -> Result<Vec<RelatedApp>> {
+        self.cached_by(
+            |cache| &mut cache.related_apps,
+            endpoint.to_owned(),
+            || self.inner.related_apps(endpoint),
+        )
+    }
+
+    fn related_app(&self, relation_id: &str) -> Result<RelatedApp> {
+        self.cached_by(
+            |cache| &mut cache.related_app,
+            relation_id.to_owned(),
+            || self.inner.related_app(relation_id),
+        )
+    }
+
+    fn relation_id(&self) -> Result<Option<String>> {
+        self.inner.relation_id()
+    }
+
+    fn relation_model_uuid(&self, app: &RelatedApp) -> Result<String> {
+        self.cached_by(
+            |cache| &mut cache.relation_model_uuids,
+            app.relation_id().to_owned(),
+            || self.inner.relation_model_uuid(app),
+        )
+    }
+
+    fn relation_get_app(&self, app: &RelatedApp) -> Result<HashMap<String, String>> {
+        self.cached_by(
+            |cache| &mut cache.databags,
+            (app.relation_id().to_owned(), Databag::App(app.name.clone())),
+            || self.inner.relation_get_app(app),
+        )
+    }
+
+    fn relation_get_unit(&self, unit: &RelatedUnit) -> Result<HashMap<String, String>> {
+        self.cached_by(
+            |cache| &mut cache.databags,
+            (
+                unit.relation_id().to_owned(),
+                Databag::Unit(unit.name.clone()),
+            ),
+            || self.inner.relation_get_unit(unit),
+        )
+    }
+
+    fn relation_get_local_app(&self, app: &RelatedApp) -> Result<HashMap<String, String>> {
+        self.cached_by(
+            |cache| &mut cache.databags,
+            (app.relation_id().to_owned(), self.local_app()?),
+            || self.inner.relation_get_local_app(app),
+        )
+    }
+
+    fn relation_get_local_unit(&self, app: &RelatedApp) -> Result<HashMap<String, String>> {
+        self.cached_by(
+            |cache| &mut cache.databags,
+            (app.relation_id().to_owned(), self.local_unit()?),
+            || self.inner.relation_get_local_unit(app),
+        )
+    }
+
+    fn relation_set_unit(&self, app: &RelatedApp, key: &str, value: &str) -> Result<()> {
+        self.invalidate_databag(app, self.local_unit()?);
+        self.inner.relation_set_unit(app, key, value)
+    }
+
+    fn relation_set_app(&self, app: &RelatedApp, key: &str, value: &str) -> Result<()> {
+        self.invalidate_databag(app, self.local_app()?);
+        self.inner.relation_set_app(app, key, value)
+    }
+
+    fn relation_set_many(
+        &self,
+        app: &RelatedApp,
+        data: &HashMap<String, Option<String>>,
+        on_app: bool,
+    ) -> Result<()> {
+        let databag = if on_app {
+            self.local_app()?
+        } else {
+            self.local_unit()?
+        };
+        self.invalidate_databag(app, databag);
+        self.inner.relation_set_many(app, data, on_app)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{related_app, FakeBackend};
+
+    fn backend() -> CachingBackend<FakeBackend> {
+        CachingBackend::new(FakeBackend::default())
+    }
+
+    fn data(key: &str, value: &str) -> HashMap<String, Option<String>> {
+        HashMap::from([(key.to_owned(), Some(value.to_owned()))])
+    }
+
+    #[test]
+    fn leader_set_clears_leader_data() {
+        let backend = backend();
+        backend.leader_get().unwrap();
+        backend.leader_get().unwrap();
+        assert_eq!(backend.inner.calls("leader_get"), 1);
+
+        backend.leader_set("key", "value").unwrap();
+        assert_eq!(backend.leader_get().unwrap()["key"], "value");
+        assert_eq!(backend.inner.calls("leader_get"), 2);
+    }
+
+    #[test]
+    fn port_changes_clear_opened_ports() {
+        let backend = backend();
+        backend.opened_ports().unwrap();
+        backend.opened_ports().unwrap();
+        assert_eq!(backend.inner.calls("opened_ports"), 1);
+
+        backend.open_port(&Port::Tcp(80), vec![]).unwrap();
+        assert_eq!(backend.opened_ports().unwrap().len(), 1);
+        assert_eq!(backend.inner.calls("opened_ports"), 2);
+
+        backend.close_port(&Port::Tcp(80), vec![]).unwrap();
+        assert!(backend.opened_ports().unwrap().is_empty());
+        assert_eq!(backend.inner.calls("opened_ports"), 3);
+    }
+
+    #[test]
+    fn unit_state_changes_clear_unit_state() {
+        let backend = backend();
+        backend.get_unit_state().unwrap();
+        backend.get_unit_state().unwrap();
+        assert_eq!(backend.inner.calls("get_unit_state"), 1);
+
+        backend.set_unit_state("key", "value").unwrap();
+        assert_eq!(backend.get_unit_state().unwrap()["key"], "value");
+        assert_eq!(backend.inner.calls("get_unit_state"), 2);
+
+        backend.delete_unit_state("key").unwrap();
+        assert!(backend.get_unit_state().unwrap().is_empty());
+        assert_eq!(backend.inner.calls("get_unit_state"), 3);
+    }
+
+    #[test]
+    fn relation_set_many_clears_only_the_written_databag() {
+        let backend = backend();
+        let app = related_app("db", "database", "database:1");
+        let read_both = || {
+            backend.relation_get_local_unit(&app).unwrap();
+            backend.relation_get_local_app(&app).unwrap();
+        };
+        read_both();
+        read_both();
+        assert_eq!(backend.inner.calls("relation_get"), 2);
+
+        backend
+            .relation_set_many(&app, &data("key", "unit"), false)
+            .unwrap();
+        read_both();
+        assert_eq!(backend.inner.calls("relation_get"), 3);
+        assert_eq!(
+            backend.relation_get_local_unit(&app).unwrap()["key"],
+            "unit"
+        );
+
+        backend
+            .relation_set_many(&app, &data("key", "app"), true)
+            .unwrap();
+        read_both();
+        assert_eq!(backend.inner.calls("relation_get"), 4);
+        assert_eq!(backend.relation_get_local_app(&app).unwrap()["key"], "app");
+    }
+
+    #[test]
+    fn peer_relation_shares_the_app_databag() {
+        let backend = backend();
+        let peers = related_app("app", "peers", "peers:1");
+        backend.relation_get_app(&peers).unwrap();
+        backend.relation_get_local_app(&peers).unwrap();
+        assert_eq!(backend.inner.calls("relation_get"), 1);
+
+        backend.relation_set_app(&peers, "key", "value").unwrap();
+        assert_eq!(backend.relation_get_app(&peers).unwrap()["key"], "value");
+        assert_eq!(
+            backend.relation_get_local_app(&peers).unwrap()["key"],
+            "value"
+        );
+        assert_eq!(backend.inner.calls("relation_get"), 2);
+    }
+}
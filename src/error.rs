@@ -1,3 +1,5 @@
+use crate::types::{JujuFeature, JujuVersion};
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("io error: {0}")]
@@ -6,6 +8,9 @@ pub enum Error {
     #[error("json deserialization error: {0}")]
     JsonError(#[from] serde_json::error::Error),
 
+    #[error("yaml deserialization error: {0}")]
+    YamlError(#[from] serde_yaml::Error),
+
     #[error("error reading environment variable: {0}")]
     VarError(#[from] std::env::VarError),
 
@@ -14,6 +19,67 @@ pub enum Error {
 
     #[error("error reading from stdin")]
     StdinError(),
+
+    #[error("{tool} failed: {stderr}")]
+    HookToolError { tool: String, stderr: String },
+
+    #[error("permission denied reading secret {0}")]
+    SecretPermissionDenied(String),
+
+    #[error("secret {0} not found")]
+    SecretNotFound(String),
+
+    #[error("this unit is not the leader")]
+    NotLeader,
+
+    #[error("{feature} requires juju {required} or newer")]
+    UnsupportedByJuju {
+        feature: JujuFeature,
+        required: JujuVersion,
+    },
+
+    #[error("invalid juju version: {0:?}")]
+    InvalidJujuVersion(String),
+
+    #[error("invalid relation id: {0:?}")]
+    InvalidRelationId(String),
+
+    #[error("metrics can only be added during the collect-metrics hook")]
+    MetricsUnavailable,
+
+    #[error("invalid metric: {0}")]
+    InvalidMetric(String),
+
+    #[error("invalid port: {0}")]
+    InvalidPort(String),
+
+    #[error("resource {0} has not been uploaded")]
+    ResourceMissing(String),
+
+    #[error("resource {0} is empty")]
+    ResourceEmpty(String),
+
+    #[error("resource {name} has SHA-384 fingerprint {actual}, expected {expected}")]
+    ResourceFingerprintMismatch {
+        name: String,
+        expected: String,
+        actual: String,
+    },
+
+    #[error("juju does not allow rebooting from an action")]
+    RebootInAction,
+
+    #[error("invalid base: {0:?}")]
+    InvalidBase(String),
+
+    #[error("series upgrade helpers can only be used during the pre-series-upgrade and post-series-upgrade hooks")]
+    SeriesUpgradeUnavailable,
+
+    #[error("handler panicked: {0}")]
+    HandlerPanicked(String),
+
+    #[error("action exceeded its deadline of {}s", .0.as_secs())]
+    ActionDeadlineExceeded(std::time::Duration),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
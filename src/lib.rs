@@ -1,24 +1,55 @@
+use std::{
+    any::Any,
+    backtrace::Backtrace,
+    panic::{self, AssertUnwindSafe},
+    str::FromStr,
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
 use anyhow::Result;
 
 pub mod backend;
+pub mod caching;
+pub mod env;
 pub mod error;
+pub mod logging;
+pub mod migration;
 pub mod model;
+pub mod schema;
+#[cfg(test)]
+mod testing;
 pub mod types;
 
 use backend::Backend;
-use model::{ActionModel, EventModel};
+use migration::Migration;
+use model::{ActionModel, EventModel, Logger, ValidatedConfig};
+use serde_json::{Map, Value};
 use types::{ActionResult, Event, LogLevel, Status};
 
+/// Loads the charm config and returns any problems found with it.
+type ConfigValidator<B> = fn(&B) -> error::Result<Vec<String>>;
+
 pub struct Framework<A, B> {
-    backend: B,
+    backend: Arc<B>,
     event_handler: fn(EventModel<B>) -> Result<Status>,
     // TODO: nested result isn't very ergonomic or readable
     action_handler: fn(ActionModel<A, B>) -> Result<ActionResult>,
+    action_timeout: Option<Duration>,
+    config_validator: Option<ConfigValidator<B>>,
+    log_level_option: Option<&'static str>,
+    dump_env: bool,
+    redacted_env_patterns: Vec<String>,
+    migrations: Vec<Migration<B>>,
 }
 
 impl<A, B> Framework<A, B>
 where
-    B: Backend,
+    B: Backend + Send + Sync + 'static,
     A: serde::de::DeserializeOwned,
 {
     pub fn new(
@@ -27,12 +58,101 @@ where
         action_handler: fn(ActionModel<A, B>) -> Result<ActionResult>,
     ) -> Self {
         Self {
-            backend,
+            backend: Arc::new(backend),
             event_handler,
             action_handler,
+            action_timeout: None,
+            config_validator: None,
+            log_level_option: None,
+            dump_env: false,
+            redacted_env_patterns: env::DEFAULT_REDACTED_PATTERNS
+                .iter()
+                .map(|pattern| pattern.to_string())
+                .collect(),
+            migrations: vec![],
         }
     }
 
+    /// Register migrations to run on upgrade-charm, before config validation and the event handler.
+    /// Each migration runs once per unit (or once per application, on the leader),
+    /// in order of version; see `migration::Migration`.
+    /// On install, all migrations are recorded as already applied.
+    pub fn with_migrations(mut self, migrations: Vec<Migration<B>>) -> Self {
+        self.migrations.extend(migrations);
+        self
+    }
+
+    /// Log every environment variable at DEBUG level at the start of each hook or action.
+    /// Values of variables that look sensitive are redacted (see `redact_env_vars`).
+    /// This is off by default;
+    /// a summary of the relevant `JUJU_*` context variables is always logged instead.
+    pub fn with_env_dump(mut self) -> Self {
+        self.dump_env = true;
+        self
+    }
+
+    /// Redact the values of environment variables with names matching any of `patterns`
+    /// when dumping the environment, in addition to `env::DEFAULT_REDACTED_PATTERNS`.
+    /// Patterns are case-insensitive, and `*` matches any sequence of characters
+    /// (eg. `MYAPP_*_PASS`).
+    pub fn redact_env_vars(mut self, patterns: &[&str]) -> Self {
+        self.redacted_env_patterns
+            .extend(patterns.iter().map(|pattern| pattern.to_string()));
+        self
+    }
+
+    /// Read the log level threshold from the charm config option `name`
+    /// (one of `debug`, `info`, `warning`, or `error`).
+    /// This applies to `model.log`, and to records from the `log` and `tracing` crates.
+    /// If the option is not set, or not a valid level, everything is logged.
+    pub fn with_log_level_option(mut self, name: &'static str) -> Self {
+        self.log_level_option = Some(name);
+        self
+    }
+
+    /// Resolve the log level threshold from the charm config, if configured.
+    fn log_level(&self) -> error::Result<LogLevel> {
+        let Some(name) = self.log_level_option else {
+            return Ok(LogLevel::Debug);
+        };
+        let config: Value = self.backend.config()?;
+        match config
+            .get(name)
+            .and_then(Value::as_str)
+            .map(LogLevel::from_str)
+        {
+            Some(Ok(level)) => Ok(level),
+            Some(Err(e)) => {
+                self.backend
+                    .log(&format!("invalid {name} option: {e}"), LogLevel::Warning)?;
+                Ok(LogLevel::Debug)
+            }
+            None => Ok(LogLevel::Debug),
+        }
+    }
+
+    /// Validate the charm config as `C` before running the event handler.
+    /// If the config can't be deserialised or `C::validate` reports problems,
+    /// the unit is set to blocked with a message listing them,
+    /// and the event handler is not run.
+    ///
+    /// Teardown events (`Remove`, `Stop`, `RelationBroken`, and `StorageDetached`) are exempt,
+    /// so the charm can still clean up while its config is invalid.
+    pub fn with_validated_config<C: ValidatedConfig>(mut self) -> Self {
+        self.config_validator = Some(model::validate_config::<B, C>);
+        self
+    }
+
+    /// Fail actions that run for longer than `timeout`.
+    /// The handler can check the deadline cooperatively with `ActionModel::check_deadline` or
+    /// `ActionModel::progress`.
+    /// If the handler still hasn't returned by the deadline,
+    /// the action is failed and the process exits.
+    pub fn with_action_timeout(mut self, timeout: Duration) -> Self {
+        self.action_timeout = Some(timeout);
+        self
+    }
+
     /// Process the current event, hook, or action from the environment,
     /// populating local state, and calling the handler functions as appropriate.
     /// `event_handler` must return a status - this status will be the final status set before
@@ -40,20 +160,46 @@ where
     /// The event handler may explicitly set a status during execution.
     /// This may be useful in the case of a long running hook (eg. set a maintenance ongoing status at
     /// the beginning).
+    ///
+    /// If a handler panics, the panic message and a backtrace are written to the juju log,
+    /// the action is failed (when running an action), and an error is returned
+    /// so that the process exits with a failure code.
     pub fn execute(self) -> Result<()> {
-        // debug log all env vars for testing purposes
-        for (key, value) in std::env::vars() {
-            self.backend
-                .log(format!("{key}: {value}").as_str(), LogLevel::Debug)?;
+        let log_level = self.log_level()?;
+        logging::install(self.backend.clone(), log_level);
+        self.install_panic_hook();
+        // the framework's own messages respect the configured threshold too
+        let log = Logger::new(self.backend.as_ref(), log_level);
+
+        if log.threshold() <= LogLevel::Debug {
+            log.debug(&format!("juju context: {}", env::context_summary()))?;
+            if self.dump_env {
+                for (key, value) in std::env::vars() {
+                    let value = env::redact(&key, &value, &self.redacted_env_patterns);
+                    log.debug(&format!("{key}: {value}"))?;
+                }
+            }
+        }
+
+        match self.backend.model_info().map(|info| info.juju_version) {
+            Ok(Some(version)) if version < types::MINIMUM_JUJU_VERSION => {
+                log.warn(&format!(
+                    "juju {version} is older than the minimum supported version {}",
+                    types::MINIMUM_JUJU_VERSION
+                ))?;
+            }
+            Ok(None) => {
+                log.warn(
+                    "could not determine the juju version; assuming all features are supported",
+                )?;
+            }
+            _ => {}
         }
 
         // ref. https://juju.is/docs/juju/charm-environment-variables for logic
         let hook_name = self.backend.hook_name()?;
         if !hook_name.is_empty() {
-            self.backend.log(
-                format!("running handlers for {hook_name} hook").as_str(),
-                LogLevel::Debug,
-            )?;
+            log.debug(&format!("running handlers for {hook_name} hook"))?;
 
             let event = match hook_name.as_str() {
                 "collect-metrics" => Event::CollectMetrics,
@@ -95,23 +241,125 @@ where
                 }
             };
 
-            let model = EventModel::new(&self.backend, event);
+            if matches!(event, Event::PebbleCustomNotice) {
+                let feature = types::JujuFeature::PebbleNotices;
+                if let Some(version) = self.backend.model_info()?.juju_version {
+                    if !version.supports(feature) {
+                        return Err(error::Error::UnsupportedByJuju {
+                            feature,
+                            required: feature.required_version(),
+                        }
+                        .into());
+                    }
+                }
+            }
+
+            // A new unit has no old data to migrate.
+            // Record this before anything that can skip the handler,
+            // so a unit blocked on install doesn't run every migration on its first upgrade.
+            if matches!(event, Event::Install) {
+                migration::record_installed(self.backend.as_ref(), &self.migrations)?;
+            }
+
+            let is_config_changed = matches!(event, Event::ConfigChanged);
+            let is_upgrade = matches!(event, Event::UpgradeCharm);
+            let is_teardown = matches!(
+                event,
+                Event::Remove | Event::Stop | Event::RelationBroken(_) | Event::StorageDetached(_)
+            );
+            let model = EventModel::new(
+                self.backend.as_ref(),
+                event,
+                self.backend.model_info()?,
+                log_level,
+            );
 
-            let status = (self.event_handler)(model)?;
+            // Migrate before validating the config: a new charm version may add options that
+            // block the unit, and later hooks must not see unmigrated data in the meantime.
+            if is_upgrade {
+                if let Err(e) =
+                    catch_panic(|| migration::run(self.backend.as_ref(), &model, &self.migrations))
+                {
+                    return self.block_on_secret_error(e);
+                }
+            }
+
+            if let Some(validate_config) = self.config_validator.filter(|_| !is_teardown) {
+                let problems = validate_config(self.backend.as_ref())?;
+                if !problems.is_empty() {
+                    let msg = format!("invalid config: {}", problems.join("; "));
+                    self.backend.log(&msg, LogLevel::Error)?;
+                    self.backend.set_status(Status::Blocked(&msg))?;
+                    return Ok(());
+                }
+            }
+
+            let status = match catch_panic(|| (self.event_handler)(model)) {
+                Ok(status) => status,
+                Err(e) => return self.block_on_secret_error(e),
+            };
             self.backend.set_status(status)?;
+            if is_config_changed {
+                model::save_config_snapshot(self.backend.as_ref())?;
+            }
             return Ok(());
         }
 
         let action_name = self.backend.action_name()?;
         if !action_name.is_empty() {
-            self.backend.log(
-                format!("running handler for {action_name} action").as_str(),
-                LogLevel::Debug,
-            )?;
-            let action: A = self.backend.action()?;
-            let model = ActionModel::new(&self.backend, action);
-
-            let result = (self.action_handler)(model)?;
+            log.debug(&format!("running handler for {action_name} action"))?;
+            let params = self.backend.action_params()?;
+            if let Some(schema) = self.backend.action_schema(&action_name)? {
+                let violations = schema::validate(&schema, &params);
+                if !violations.is_empty() {
+                    self.backend.set_action_fail(&format!(
+                        "invalid parameters for {action_name} action: {}",
+                        violations.join("; ")
+                    ))?;
+                    return Ok(());
+                }
+            }
+
+            // The action type is expected to be an enum with a variant for each action,
+            // so wrap the params to deserialise into the variant matching the action name.
+            let action_value = Value::Object({
+                let mut map = Map::new();
+                map.insert(action_name.clone(), params);
+                map
+            });
+            let action: A = match serde_json::from_value(action_value) {
+                Ok(action) => action,
+                Err(e) => {
+                    self.backend.set_action_fail(&format!(
+                        "could not parse parameters for {action_name} action: {e}"
+                    ))?;
+                    return Ok(());
+                }
+            };
+            let model = ActionModel::new(
+                self.backend.as_ref(),
+                action,
+                self.backend.action_id()?,
+                self.backend.model_info()?,
+                self.action_timeout,
+                log_level,
+            );
+
+            let result = match self.run_action_handler(model) {
+                Ok(result) => result,
+                Err(e) => match e.downcast_ref::<error::Error>() {
+                    Some(error::Error::ActionDeadlineExceeded(_)) => {
+                        self.backend.set_action_fail(&e.to_string())?;
+                        return Ok(());
+                    }
+                    Some(error::Error::HandlerPanicked(_)) => {
+                        // Still return the error so the process exits with a failure code.
+                        self.backend.set_action_fail(&e.to_string())?;
+                        return Err(e);
+                    }
+                    _ => return Err(e),
+                },
+            };
             match result {
                 Ok(data) => {
                     self.backend.set_action_result(data)?;
@@ -124,4 +372,161 @@ where
         }
         Ok(())
     }
+
+    /// Log panics from the charm code to the juju log, including a backtrace,
+    /// before running the default panic hook.
+    /// Handle an error from charm code run for an event.
+    /// Errors reading secrets are down to how the charm was deployed,
+    /// so the unit is set to blocked with instructions rather than failing the hook.
+    /// Other errors are returned.
+    fn block_on_secret_error(&self, e: anyhow::Error) -> Result<()> {
+        let msg = match e.downcast_ref::<error::Error>() {
+            Some(error::Error::SecretPermissionDenied(uri)) => {
+                format!("permission denied reading secret {uri}; grant it with `juju grant-secret`")
+            }
+            Some(error::Error::SecretNotFound(uri)) => {
+                format!("secret {uri} not found; check the charm config")
+            }
+            _ => return Err(e),
+        };
+        self.backend.log(&msg, LogLevel::Error)?;
+        self.backend.set_status(Status::Blocked(&msg))?;
+        Ok(())
+    }
+
+    fn install_panic_hook(&self) {
+        let backend = self.backend.clone();
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let _ = backend.log(
+                &format!(
+                    "charm code panicked: {info}\n{}",
+                    Backtrace::force_capture()
+                ),
+                LogLevel::Error,
+            );
+            default_hook(info);
+        }));
+    }
+
+    /// Run the action handler, enforcing the action timeout if one is configured.
+    fn run_action_handler(&self, model: ActionModel<A, B>) -> Result<ActionResult> {
+        let Some(timeout) = self.action_timeout else {
+            return catch_panic(|| (self.action_handler)(model));
+        };
+
+        let backend = self.backend.as_ref();
+        let (done_tx, done_rx) = mpsc::channel::<()>();
+        thread::scope(|scope| {
+            scope.spawn(move || {
+                // The sender is dropped when the handler returns,
+                // so a timeout here means the handler is still running.
+                if let Err(RecvTimeoutError::Timeout) = done_rx.recv_timeout(timeout) {
+                    let msg = error::Error::ActionDeadlineExceeded(timeout).to_string();
+                    let _ = backend.log(&msg, LogLevel::Error);
+                    let _ = backend.set_action_fail(&msg);
+                    // The failure has been recorded with action-fail,
+                    // so exit as for any other handled action failure.
+                    std::process::exit(0);
+                }
+            });
+            let result = catch_panic(|| (self.action_handler)(model));
+            drop(done_tx);
+            result
+        })
+    }
+}
+
+/// Call `f`, converting a panic into `Error::HandlerPanicked`.
+/// The panic hook installed by the framework has already logged the details by then.
+fn catch_panic<T>(f: impl FnOnce() -> Result<T>) -> Result<T> {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        Err(error::Error::HandlerPanicked(panic_message(&payload)).into())
+    })
+}
+
+fn panic_message(payload: &Box<dyn Any + Send>) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "unknown panic payload".to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migration::MigrationScope;
+    use crate::testing::{FakeBackend, FakeState};
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Deserialize)]
+    struct Config {
+        region: String,
+    }
+
+    impl ValidatedConfig for Config {
+        fn validate(&self) -> std::result::Result<(), Vec<String>> {
+            if self.region.is_empty() {
+                return Err(vec!["region must be set".to_owned()]);
+            }
+            Ok(())
+        }
+    }
+
+    fn event_handler(model: EventModel<FakeBackend>) -> Result<Status> {
+        model.unit.state.set("handler-ran", "yes")?;
+        Ok(Status::Active(""))
+    }
+
+    fn action_handler(_model: ActionModel<Value, FakeBackend>) -> Result<ActionResult> {
+        Ok(Ok(Default::default()))
+    }
+
+    fn migrate(model: &EventModel<FakeBackend>) -> Result<()> {
+        model.unit.state.set("migrated", "yes")?;
+        Ok(())
+    }
+
+    #[test]
+    fn migrations_run_on_upgrade_with_invalid_config() {
+        let backend = FakeBackend::new(FakeState {
+            hook_name: "upgrade-charm".to_owned(),
+            config: json!({"region": ""}),
+            ..Default::default()
+        });
+        let framework = Framework::new(backend, event_handler, action_handler)
+            .with_validated_config::<Config>()
+            .with_migrations(vec![Migration {
+                version: 1,
+                scope: MigrationScope::Unit,
+                description: "test migration",
+                run: migrate,
+            }]);
+        let backend = framework.backend.clone();
+        framework.execute().unwrap();
+
+        let state = backend.state();
+        assert_eq!(
+            state.unit_state.get("migrated").map(String::as_str),
+            Some("yes")
+        );
+        assert_eq!(
+            state
+                .unit_state
+                .get("rusty-charm-framework.unit-migration-version")
+                .map(String::as_str),
+            Some("1")
+        );
+        assert_eq!(backend.calls("set_status"), 1);
+        // the config is still invalid, so the handler is skipped
+        assert!(!state.unit_state.contains_key("handler-ran"));
+        assert_eq!(
+            state.status.as_deref(),
+            Some("blocked: invalid config: region must be set")
+        );
+    }
 }
@@ -0,0 +1,156 @@
+// Forward records from the `log` and `tracing` crates to the juju log,
+// so libraries used by the charm can log as usual.
+use std::{fmt::Write, sync::Arc};
+
+use tracing::{
+    field::{Field, Visit},
+    Event, Subscriber,
+};
+use tracing_subscriber::{layer::Context, prelude::*, Layer};
+
+use crate::backend::Backend;
+use crate::types::LogLevel;
+
+impl From<log::Level> for LogLevel {
+    fn from(level: log::Level) -> Self {
+        match level {
+            log::Level::Error => LogLevel::Error,
+            log::Level::Warn => LogLevel::Warning,
+            log::Level::Info => LogLevel::Info,
+            log::Level::Debug | log::Level::Trace => LogLevel::Debug,
+        }
+    }
+}
+
+impl From<&tracing::Level> for LogLevel {
+    fn from(level: &tracing::Level) -> Self {
+        match *level {
+            tracing::Level::ERROR => LogLevel::Error,
+            tracing::Level::WARN => LogLevel::Warning,
+            tracing::Level::INFO => LogLevel::Info,
+            _ => LogLevel::Debug,
+        }
+    }
+}
+
+impl From<LogLevel> for log::LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Debug => log::LevelFilter::Trace,
+            LogLevel::Info => log::LevelFilter::Info,
+            LogLevel::Warning => log::LevelFilter::Warn,
+            LogLevel::Error => log::LevelFilter::Error,
+        }
+    }
+}
+
+/// A `log::Log` implementation that sends records to `Backend::log`.
+pub struct JujuLogger<B> {
+    backend: Arc<B>,
+    threshold: LogLevel,
+}
+
+impl<B> JujuLogger<B> {
+    pub fn new(backend: Arc<B>, threshold: LogLevel) -> Self {
+        Self { backend, threshold }
+    }
+}
+
+impl<B> log::Log for JujuLogger<B>
+where
+    B: Backend + Send + Sync,
+{
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        LogLevel::from(metadata.level()) >= self.threshold
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            // Errors can't be reported from here; the juju log is where they would go.
+            let _ = self.backend.log(
+                &format!("{}: {}", record.target(), record.args()),
+                record.level().into(),
+            );
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// A `tracing_subscriber` layer that sends events to `Backend::log`.
+/// Span context is not included.
+pub struct JujuLogLayer<B> {
+    backend: Arc<B>,
+    threshold: LogLevel,
+}
+
+impl<B> JujuLogLayer<B> {
+    pub fn new(backend: Arc<B>, threshold: LogLevel) -> Self {
+        Self { backend, threshold }
+    }
+}
+
+impl<S, B> Layer<S> for JujuLogLayer<B>
+where
+    S: Subscriber,
+    B: Backend + Send + Sync + 'static,
+{
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let level = LogLevel::from(metadata.level());
+        if level < self.threshold {
+            return;
+        }
+
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        let _ = self.backend.log(
+            &format!(
+                "{}: {}{}",
+                metadata.target(),
+                visitor.message,
+                visitor.fields
+            ),
+            level,
+        );
+    }
+}
+
+/// Collects the message and other fields of a tracing event into strings.
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+    fields: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{value:?}");
+        } else {
+            let _ = write!(self.fields, " {}={value:?}", field.name());
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            let _ = write!(self.fields, " {}={value:?}", field.name());
+        }
+    }
+}
+
+/// Install the `log` logger and the global `tracing` subscriber,
+/// forwarding records at or above `threshold` to the juju log.
+/// Does nothing for either if a logger or subscriber has already been installed.
+pub(crate) fn install<B>(backend: Arc<B>, threshold: LogLevel)
+where
+    B: Backend + Send + Sync + 'static,
+{
+    if log::set_boxed_logger(Box::new(JujuLogger::new(backend.clone(), threshold))).is_ok() {
+        log::set_max_level(threshold.into());
+    }
+    let subscriber = tracing_subscriber::registry().with(JujuLogLayer::new(backend, threshold));
+    let _ = tracing::subscriber::set_global_default(subscriber);
+}
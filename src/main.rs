@@ -3,7 +3,8 @@
 use anyhow::Result;
 use rusty_charm_framework::{
     backend::{Backend, JujuBackend},
-    model::{ActionModel, EventModel},
+    caching::CachingBackend,
+    model::{ActionModel, EventModel, ValidatedConfig},
     types::{ActionResult, ActionResultKey, ActionValue, Event, Status},
     Framework,
 };
@@ -31,6 +32,15 @@ struct Config {
     region: String,
 }
 
+impl ValidatedConfig for Config {
+    fn validate(&self) -> std::result::Result<(), Vec<String>> {
+        if self.region.is_empty() {
+            return Err(vec!["region option cannot be empty".to_owned()]);
+        }
+        Ok(())
+    }
+}
+
 fn event_handler(model: EventModel<impl Backend>) -> Result<Status> {
     let config: Config = model.unit.config()?;
     model
@@ -38,15 +48,21 @@ fn event_handler(model: EventModel<impl Backend>) -> Result<Status> {
         .info(format!("region config = {}", config.region).as_str())?;
     match model.event {
         Event::UpdateStatus => {
-            if config.region.is_empty() {
-                return Ok(Status::Blocked("region option cannot be empty"));
-            } else {
-                return Ok(Status::Active(""));
-            }
+            // the framework already blocks on invalid config, so the region is known to be set
+            return Ok(Status::Active(""));
         }
         Event::Install => {
+            // records from the log crate are forwarded to the juju log
+            log::info!("installing on {}", std::env::consts::ARCH);
             model.status.active("Install hook completed")?;
         }
+        Event::ConfigChanged => {
+            if let Some(change) = model.unit.config_diff()?.changed.get("region") {
+                model.log.info(
+                    format!("region changed from {} to {}", change.old, change.new).as_str(),
+                )?;
+            }
+        }
         _ => {}
     }
 
@@ -59,15 +75,18 @@ fn action_handler(model: ActionModel<Action, impl Backend>) -> Result<ActionResu
         .debug(&format!("deserialised action: {:?}", model.action))?;
     match model.action {
         Action::Log {} => {
-            model.action_log("Logging a message at the beginning of the handler.")?;
+            model.action_log(&format!(
+                "Logging a message at the beginning of the handler for action {} on {}.",
+                model.id, model.info.unit_name
+            ))?;
 
-            model.action_log("Sleeping for 1 second")?;
+            model.progress(0, 2, "Sleeping for 1 second")?;
             thread::sleep(time::Duration::from_secs(1));
 
-            model.action_log("Sleeping for another second")?;
+            model.progress(1, 2, "Sleeping for another second")?;
             thread::sleep(time::Duration::from_secs(1));
 
-            model.action_log("Done!")?;
+            model.progress(2, 2, "Done!")?;
 
             Ok(Ok(HashMap::new()))
         }
@@ -141,6 +160,15 @@ fn action_handler(model: ActionModel<Action, impl Backend>) -> Result<ActionResu
 
 fn main() -> Result<()> {
     // dependency injection for the framework for easier unit testing
-    let charm = Framework::new(JujuBackend {}, event_handler, action_handler);
+    let charm = Framework::new(
+        CachingBackend::new(JujuBackend {}),
+        event_handler,
+        action_handler,
+    )
+    .with_action_timeout(time::Duration::from_secs(60))
+    .with_validated_config::<Config>()
+    .with_log_level_option("log-level")
+    // this is a test charm, so dump the environment to help debugging
+    .with_env_dump();
     charm.execute()
 }
@@ -0,0 +1,134 @@
+// Migrations of charm data between charm versions, run on upgrade-charm.
+use crate::backend::Backend;
+use crate::error::Result;
+use crate::model::EventModel;
+
+/// Unit state key for the version of the last unit migration applied.
+const UNIT_VERSION_KEY: &str = "rusty-charm-framework.unit-migration-version";
+
+/// Leader data key for the version of the last app migration applied.
+const APP_VERSION_KEY: &str = "rusty-charm-framework.app-migration-version";
+
+/// What a migration applies to, which determines where its progress is recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationScope {
+    /// Data belonging to each unit, like unit state, the unit's relation data, or files on disk.
+    /// Runs on every unit, and the last version applied is recorded in unit state.
+    Unit,
+    /// Data shared by the application, like leader data or the app relation data.
+    /// Only runs on the leader, and the last version applied is recorded in leader data.
+    App,
+}
+
+/// A migration of charm data, to be run once when upgrading from a charm version that predates
+/// it.
+/// Register migrations with `Framework::with_migrations`;
+/// pending migrations are run in order of version on `Event::UpgradeCharm`,
+/// before config validation and the event handler, so a new charm revision whose
+/// config doesn't validate yet still migrates its state.
+///
+/// If a migration fails, the hook errors and juju retries the upgrade-charm hook,
+/// running the migrations again from the first pending one.
+/// Changes made by earlier migrations in the same hook may or may not have been kept,
+/// so migrations should be safe to run more than once.
+///
+/// ```ignore
+/// fn rename_db_key(model: &EventModel<impl Backend>) -> anyhow::Result<()> {
+///     if let Some(value) = model.unit.state.read()?.get("db") {
+///         model.unit.state.set("database", value)?;
+///         model.unit.state.del("db")?;
+///     }
+///     Ok(())
+/// }
+///
+/// Framework::new(JujuBackend {}, event_handler, action_handler).with_migrations(vec![
+///     Migration { version: 1, scope: MigrationScope::Unit, description: "rename db key", run: rename_db_key },
+/// ]);
+/// ```
+pub struct Migration<B> {
+    /// Must be unique within the scope, and increase with each new migration.
+    pub version: u32,
+    pub scope: MigrationScope,
+    /// Short description for the logs.
+    pub description: &'static str,
+    pub run: fn(&EventModel<B>) -> anyhow::Result<()>,
+}
+
+/// Get the last migration version applied for `scope`, or 0 if none have been applied.
+fn applied_version<B: Backend>(backend: &B, scope: MigrationScope) -> Result<u32> {
+    let version = match scope {
+        MigrationScope::Unit => backend.get_unit_state()?.remove(UNIT_VERSION_KEY),
+        MigrationScope::App => backend.leader_get()?.remove(APP_VERSION_KEY),
+    };
+    Ok(version
+        .and_then(|version| version.parse().ok())
+        .unwrap_or_default())
+}
+
+fn record_version<B: Backend>(backend: &B, scope: MigrationScope, version: u32) -> Result<()> {
+    match scope {
+        MigrationScope::Unit => backend.set_unit_state(UNIT_VERSION_KEY, &version.to_string()),
+        MigrationScope::App => backend.leader_set(APP_VERSION_KEY, &version.to_string()),
+    }
+}
+
+/// Run the migrations that have not been applied yet, in order of version.
+/// App migrations are skipped if this unit is not the leader.
+pub(crate) fn run<B: Backend>(
+    backend: &B,
+    model: &EventModel<B>,
+    migrations: &[Migration<B>],
+) -> anyhow::Result<()> {
+    if migrations.is_empty() {
+        return Ok(());
+    }
+    let is_leader = backend.is_leader()?;
+    let unit_version = applied_version(backend, MigrationScope::Unit)?;
+    let app_version = if is_leader {
+        applied_version(backend, MigrationScope::App)?
+    } else {
+        0
+    };
+
+    let mut pending: Vec<&Migration<B>> = migrations
+        .iter()
+        .filter(|migration| match migration.scope {
+            MigrationScope::Unit => migration.version > unit_version,
+            MigrationScope::App => is_leader && migration.version > app_version,
+        })
+        .collect();
+    pending.sort_by_key(|migration| migration.version);
+
+    for migration in pending {
+        model.log.info(&format!(
+            "running {:?} migration {}: {}",
+            migration.scope, migration.version, migration.description
+        ))?;
+        (migration.run)(model)?;
+        record_version(backend, migration.scope, migration.version)?;
+    }
+    Ok(())
+}
+
+/// Record all migrations as applied, for a freshly installed unit which has no old data to
+/// migrate.
+/// App migrations are only recorded if this unit is the leader and none have been recorded
+/// before, meaning the application is also new.
+pub(crate) fn record_installed<B: Backend>(backend: &B, migrations: &[Migration<B>]) -> Result<()> {
+    let latest = |scope| {
+        migrations
+            .iter()
+            .filter(|migration| migration.scope == scope)
+            .map(|migration| migration.version)
+            .max()
+    };
+    if let Some(version) = latest(MigrationScope::Unit) {
+        record_version(backend, MigrationScope::Unit, version)?;
+    }
+    if let Some(version) = latest(MigrationScope::App) {
+        if backend.is_leader()? && !backend.leader_get()?.contains_key(APP_VERSION_KEY) {
+            record_version(backend, MigrationScope::App, version)?;
+        }
+    }
+    Ok(())
+}
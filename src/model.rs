@@ -1,8 +1,146 @@
-use crate::types::{Event, LogLevel, RelatedApp, RelatedUnit, Status};
-use std::collections::HashMap;
+use crate::types::{
+    Base, ConfigDiff, Event, JujuVersion, LogLevel, MetricDefinition, MetricKind, MetricValue,
+    OciImage, OpenedPort, Payload, PayloadStatus, Port, RelatedApp, RelatedUnit, SecretRef, Status,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha384};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
 use crate::backend::Backend;
-use crate::error::Result;
+use crate::error::{Error, Result};
+
+/// Identity of the unit the charm code is running on, and the model it is deployed in.
+/// Populated from the environment juju runs the charm in.
+/// ref. https://juju.is/docs/juju/charm-environment-variables
+#[derive(Debug, Clone)]
+pub struct ModelInfo {
+    /// Name of this unit (eg. `rusty/0`).
+    pub unit_name: String,
+    /// Name of the deployed application this unit belongs to (not the charm name).
+    pub app_name: String,
+    /// For subordinate units, the name of the principal unit.
+    pub principal_unit: Option<String>,
+    pub model_name: String,
+    pub model_uuid: String,
+    /// Id of the machine the unit is deployed on.
+    /// Not set for kubernetes models.
+    pub machine_id: Option<String>,
+    /// Availability zone of the machine the unit is deployed on, if the cloud has them.
+    pub availability_zone: Option<String>,
+    /// Directory the charm is installed in.
+    pub charm_dir: PathBuf,
+    /// Version of the juju agent running the charm (eg. `3.6.1`).
+    /// Use `JujuVersion::supports` to check for optional features.
+    /// `None` if `JUJU_VERSION` is not set or not in a recognised format.
+    pub juju_version: Option<JujuVersion>,
+}
+
+/// Status of a unit or relation in the goal state.
+#[derive(Debug, Clone, Deserialize)]
+pub struct GoalStateStatus {
+    /// For units, eg. `waiting`, `active`, `dying`, or `error`.
+    /// For relations, eg. `joining`, `joined`, or `broken`.
+    pub status: String,
+    /// When the status was last changed, as an RFC 3339 timestamp.
+    pub since: Option<String>,
+}
+
+/// The units and relations juju expects this application to have once the model settles,
+/// as reported by the `goal-state` hook tool.
+/// Use this to wait for all expected units before bootstrapping a cluster.
+#[derive(Debug, Clone, Deserialize)]
+pub struct GoalState {
+    /// Expected units of this application, keyed by unit name.
+    #[serde(default)]
+    pub units: HashMap<String, GoalStateStatus>,
+    /// Expected related applications and units, keyed by endpoint,
+    /// then by application or unit name.
+    #[serde(default)]
+    pub relations: HashMap<String, HashMap<String, GoalStateStatus>>,
+}
+
+impl GoalState {
+    /// Names of the expected units of this application, including this unit, sorted.
+    pub fn expected_units(&self) -> Vec<&str> {
+        let mut units: Vec<&str> = self.units.keys().map(String::as_str).collect();
+        units.sort();
+        units
+    }
+
+    /// Names of the applications expected to be related on `endpoint`, sorted.
+    pub fn related_apps(&self, endpoint: &str) -> Vec<&str> {
+        self.related(endpoint, false)
+    }
+
+    /// Names of the remote units expected to be related on `endpoint`, sorted.
+    pub fn related_units(&self, endpoint: &str) -> Vec<&str> {
+        self.related(endpoint, true)
+    }
+
+    fn related(&self, endpoint: &str, units: bool) -> Vec<&str> {
+        let mut names: Vec<&str> = self
+            .relations
+            .get(endpoint)
+            .into_iter()
+            .flat_map(|related| related.keys())
+            .map(String::as_str)
+            .filter(|name| name.contains('/') == units)
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// True if every expected unit of this application has the given status (eg. `active`).
+    pub fn all_units_have_status(&self, status: &str) -> bool {
+        self.units.values().all(|unit| unit.status == status)
+    }
+}
+
+/// A charm config type that can check its values beyond what deserialisation enforces.
+/// Register it with `Framework::with_validated_config` to have the framework set a blocked
+/// status and skip the event handler when the config is invalid.
+pub trait ValidatedConfig: serde::de::DeserializeOwned {
+    /// Return a list of human readable problems with the config, if any.
+    fn validate(&self) -> std::result::Result<(), Vec<String>> {
+        Ok(())
+    }
+}
+
+/// Load the config as `C` and collect any deserialisation or validation problems.
+/// Only errors from running the hook tool itself are returned as errors.
+pub(crate) fn validate_config<B: Backend, C: ValidatedConfig>(backend: &B) -> Result<Vec<String>> {
+    match backend.config::<C>() {
+        Ok(config) => Ok(config.validate().err().unwrap_or_default()),
+        Err(Error::JsonError(e)) => Ok(vec![e.to_string()]),
+        Err(e) => Err(e),
+    }
+}
+
+/// Unit state key for the snapshot of the config as of the last successful config-changed hook.
+const CONFIG_SNAPSHOT_KEY: &str = "rusty-charm-framework.config-snapshot";
+
+/// Load the config snapshot saved by the last successful config-changed hook.
+fn config_snapshot<B: Backend>(backend: &B) -> Result<Map<String, Value>> {
+    match backend.get_unit_state()?.get(CONFIG_SNAPSHOT_KEY) {
+        Some(snapshot) => Ok(serde_json::from_str(snapshot)?),
+        None => Ok(Map::new()),
+    }
+}
+
+/// Save the current config as the snapshot for `Unit::config_diff` to compare against,
+/// if it has changed since the last snapshot.
+pub(crate) fn save_config_snapshot<B: Backend>(backend: &B) -> Result<()> {
+    let current: Map<String, Value> = backend.config()?;
+    if config_snapshot(backend)? != current {
+        backend.set_unit_state(CONFIG_SNAPSHOT_KEY, &serde_json::to_string(&current)?)?;
+    }
+    Ok(())
+}
 
 pub struct PortManager<'a, B> {
     backend: &'a B,
@@ -16,17 +154,87 @@ where
         Self { backend }
     }
 
-    pub fn ports(&self) -> Result<Vec<String>> {
+    /// Get the ports opened by this unit.
+    pub fn ports(&self) -> Result<Vec<OpenedPort>> {
         self.backend.opened_ports()
     }
 
-    pub fn open_port(&self, port: &str, endpoints: Vec<&str>) -> Result<()> {
+    /// Open `port` for the given endpoints, or for all endpoints if `endpoints` is empty.
+    /// Returns `Error::InvalidPort` without calling the hook tool if the port is invalid.
+    pub fn open_port(&self, port: &Port, endpoints: Vec<&str>) -> Result<()> {
+        port.validate()?;
         self.backend.open_port(port, endpoints)
     }
 
-    pub fn close_port(&self, port: &str, endpoints: Vec<&str>) -> Result<()> {
+    /// Close `port` for the given endpoints, or for all endpoints if `endpoints` is empty.
+    /// Returns `Error::InvalidPort` without calling the hook tool if the port is invalid.
+    pub fn close_port(&self, port: &Port, endpoints: Vec<&str>) -> Result<()> {
+        port.validate()?;
         self.backend.close_port(port, endpoints)
     }
+
+    /// Make `ports` the set of ports opened for each of the given endpoints,
+    /// or the set of ports opened for all endpoints if `endpoints` is empty.
+    /// Only the ports that differ from what is currently open are opened or closed.
+    ///
+    /// ```ignore
+    /// model.ports.set_ports(&[Port::Tcp(80), Port::Tcp(443)], vec![])?;
+    /// ```
+    pub fn set_ports(&self, ports: &[Port], endpoints: Vec<&str>) -> Result<()> {
+        for port in ports {
+            port.validate()?;
+        }
+        let opened = self.ports()?;
+        if endpoints.is_empty() {
+            self.reconcile(&opened, ports, None)
+        } else {
+            for endpoint in endpoints {
+                self.reconcile(&opened, ports, Some(endpoint))?;
+            }
+            Ok(())
+        }
+    }
+
+    /// Like `set_ports`, but with a different set of ports for each endpoint.
+    /// Endpoints not included in `ports` are left as they are.
+    pub fn set_ports_by_endpoint(&self, ports: &HashMap<&str, Vec<Port>>) -> Result<()> {
+        for port in ports.values().flatten() {
+            port.validate()?;
+        }
+        let opened = self.ports()?;
+        for (endpoint, endpoint_ports) in ports {
+            self.reconcile(&opened, endpoint_ports, Some(endpoint))?;
+        }
+        Ok(())
+    }
+
+    /// Open and close ports so that `wanted` are the ports opened for `endpoint`,
+    /// or for all endpoints if `endpoint` is `None`.
+    fn reconcile(
+        &self,
+        opened: &[OpenedPort],
+        wanted: &[Port],
+        endpoint: Option<&str>,
+    ) -> Result<()> {
+        let endpoints: Vec<&str> = endpoint.into_iter().collect();
+        let current: Vec<Port> = opened
+            .iter()
+            .filter(|opened| match endpoint {
+                Some(endpoint) => opened.endpoints.iter().any(|e| e == endpoint),
+                None => opened.endpoints.is_empty(),
+            })
+            .map(|opened| opened.port.normalized())
+            .collect();
+        let wanted: Vec<Port> = wanted.iter().map(Port::normalized).collect();
+
+        for port in current.iter().filter(|port| !wanted.contains(port)) {
+            self.backend.close_port(port, endpoints.clone())?;
+        }
+        for port in wanted.iter().filter(|port| !current.contains(port)) {
+            self.backend.open_port(port, endpoints.clone())?;
+        }
+        Ok(())
+    }
 }
 
 pub struct Unit<'a, B> {
@@ -39,6 +247,10 @@ pub struct Unit<'a, B> {
     /// model.unit.state.read().get("key1"); // -> Some("value1")
     /// ```
     pub state: UnitStateManager<'a, B>,
+    /// Methods to report the payloads (eg. containers or processes) managed by the unit.
+    pub payloads: Payloads<'a, B>,
+    /// Methods to fetch the resources attached to the charm.
+    pub resources: Resources<'a, B>,
 }
 
 impl<'a, B> Unit<'a, B>
@@ -49,6 +261,8 @@ where
         Self {
             backend,
             state: UnitStateManager::new(backend),
+            payloads: Payloads::new(backend),
+            resources: Resources::new(backend),
         }
     }
 
@@ -82,8 +296,37 @@ where
         self.backend.config()
     }
 
-    pub fn resource_path(&self, name: &str) -> Result<String> {
-        self.backend.resource_path(name)
+    /// Get the options that changed since the last successful config-changed hook.
+    /// The framework saves a snapshot of the config after the event handler returns
+    /// successfully for `Event::ConfigChanged`,
+    /// so the first config-changed hook reports every option as added.
+    ///
+    /// ```ignore
+    /// if model.unit.config_diff()?.contains_any(&["port", "bind-address"]) {
+    ///     restart_service();
+    /// }
+    /// ```
+    pub fn config_diff(&self) -> Result<ConfigDiff> {
+        let current: Map<String, Value> = self.backend.config()?;
+        Ok(ConfigDiff::between(
+            &config_snapshot(self.backend)?,
+            &current,
+        ))
+    }
+
+    /// Get the content of a secret referenced from the charm config.
+    /// If the charm has not been granted access to the secret,
+    /// this returns `Error::SecretPermissionDenied`;
+    /// when propagated from the event handler,
+    /// the framework sets a blocked status explaining how to grant access.
+    pub fn secret_content(&self, secret: &SecretRef) -> Result<HashMap<String, String>> {
+        self.backend.secret_get(secret.uri())
+    }
+
+    /// Get the units and relations juju expects this application to have.
+    /// Uses the `goal-state` hook tool.
+    pub fn goal_state(&self) -> Result<GoalState> {
+        self.backend.goal_state()
     }
 
     /// Set the workload application version.
@@ -122,7 +365,8 @@ where
     /// Load the full unit state from the server.
     /// Calls the `state-get` hook-tool.
     /// Returns a hashmap of key -> value as strings.
-    /// This is **not** cached.
+    /// This is only cached when using `caching::CachingBackend`,
+    /// in which case `set` and `del` invalidate the cached state.
     /// Changes to the returned hashmap are not updated server-side;
     /// use `UnitStateManager.set(key, value)` or `UnitStateManager.del(key)` to persist
     /// changes.
@@ -143,6 +387,119 @@ where
     }
 }
 
+/// Methods for reporting payloads to juju with the `payload-*` hook tools.
+/// Payload classes must be declared under `payloads` in the charm's metadata.
+///
+/// ```ignore
+/// let payload = Payload {
+///     class: "workers".to_owned(),
+///     kind: "docker".to_owned(),
+///     id: container_id,
+///     labels: vec![],
+/// };
+/// model.unit.payloads.register(&payload)?;
+/// model.unit.payloads.set_status(&payload, PayloadStatus::Running)?;
+/// ```
+pub struct Payloads<'a, B> {
+    backend: &'a B,
+}
+
+impl<'a, B> Payloads<'a, B>
+where
+    B: Backend,
+{
+    fn new(backend: &'a B) -> Self {
+        Self { backend }
+    }
+
+    /// Start tracking a payload.
+    /// Uses the `payload-register` hook-tool.
+    pub fn register(&self, payload: &Payload) -> Result<()> {
+        self.backend.payload_register(payload)
+    }
+
+    /// Stop tracking a payload.
+    /// Uses the `payload-unregister` hook-tool.
+    pub fn unregister(&self, payload: &Payload) -> Result<()> {
+        self.backend.payload_unregister(&payload.class, &payload.id)
+    }
+
+    /// Update the status of a registered payload.
+    /// Uses the `payload-status-set` hook-tool.
+    pub fn set_status(&self, payload: &Payload, status: PayloadStatus) -> Result<()> {
+        self.backend
+            .payload_status_set(&payload.class, &payload.id, status)
+    }
+}
+
+/// Methods to fetch the resources declared in the charm's metadata,
+/// with the `resource-get` hook tool.
+///
+/// ```ignore
+/// let installer = model.unit.resources.verified_path("installer", INSTALLER_SHA384)?;
+/// let image = model.unit.resources.oci_image("workload-image")?;
+/// ```
+pub struct Resources<'a, B> {
+    backend: &'a B,
+}
+
+impl<'a, B> Resources<'a, B>
+where
+    B: Backend,
+{
+    fn new(backend: &'a B) -> Self {
+        Self { backend }
+    }
+
+    /// Get the path to the file for the resource `name`.
+    /// Returns `Error::ResourceMissing` if the resource has not been uploaded,
+    /// or `Error::ResourceEmpty` if an empty file was uploaded
+    /// (commonly used as a placeholder when deploying).
+    pub fn path(&self, name: &str) -> Result<PathBuf> {
+        let path = self.backend.resource_path(name)?;
+        if std::fs::metadata(&path)?.len() == 0 {
+            return Err(Error::ResourceEmpty(name.to_owned()));
+        }
+        Ok(path)
+    }
+
+    /// Get the SHA-384 fingerprint of the resource `name`, as lowercase hex.
+    /// This matches the fingerprint shown by `juju charm-resources`.
+    pub fn fingerprint(&self, name: &str) -> Result<String> {
+        sha384(&self.path(name)?)
+    }
+
+    /// Get the path to the file for the resource `name`,
+    /// checking its SHA-384 fingerprint matches `expected` (hex, case-insensitive).
+    /// Returns `Error::ResourceFingerprintMismatch` if it doesn't.
+    pub fn verified_path(&self, name: &str, expected: &str) -> Result<PathBuf> {
+        let path = self.path(name)?;
+        let actual = sha384(&path)?;
+        if !actual.eq_ignore_ascii_case(expected.trim()) {
+            return Err(Error::ResourceFingerprintMismatch {
+                name: name.to_owned(),
+                expected: expected.trim().to_owned(),
+                actual,
+            });
+        }
+        Ok(path)
+    }
+
+    /// Get the registry details for the `oci-image` resource `name`.
+    /// Juju writes these to the resource file as JSON.
+    pub fn oci_image(&self, name: &str) -> Result<OciImage> {
+        let content = std::fs::read_to_string(self.path(name)?)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+}
+
+/// Hash the file at `path` with SHA-384, returning lowercase hex.
+fn sha384(path: &Path) -> Result<String> {
+    let mut hasher = Sha384::new();
+    std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
 /// Methods to update the unit status.
 /// For example:
 ///
@@ -226,38 +583,67 @@ where
     }
 }
 
+/// Methods to log to the juju log.
+/// Messages below the threshold level are dropped;
+/// see `Framework::with_log_level_option` to configure it from the charm config.
 pub struct Logger<'a, B> {
     backend: &'a B,
+    threshold: LogLevel,
 }
 
 impl<'a, B> Logger<'a, B>
 where
     B: Backend,
 {
-    fn new(backend: &'a B) -> Self {
-        Self { backend }
+    pub(crate) fn new(backend: &'a B, threshold: LogLevel) -> Self {
+        Self { backend, threshold }
+    }
+
+    /// The minimum level of messages that will be logged.
+    pub fn threshold(&self) -> LogLevel {
+        self.threshold
+    }
+
+    fn log(&self, msg: &str, level: LogLevel) -> Result<()> {
+        if level < self.threshold {
+            return Ok(());
+        }
+        self.backend.log(msg, level)
     }
 
     pub fn debug(&self, msg: &str) -> Result<()> {
-        self.backend.log(msg, LogLevel::Debug)
+        self.log(msg, LogLevel::Debug)
     }
 
     pub fn info(&self, msg: &str) -> Result<()> {
-        self.backend.log(msg, LogLevel::Info)
+        self.log(msg, LogLevel::Info)
     }
 
     pub fn warn(&self, msg: &str) -> Result<()> {
-        self.backend.log(msg, LogLevel::Warning)
+        self.log(msg, LogLevel::Warning)
     }
 
     pub fn error(&self, msg: &str) -> Result<()> {
-        self.backend.log(msg, LogLevel::Error)
+        self.log(msg, LogLevel::Error)
     }
 }
 
+/// Unit state key for the marker recorded by `EventModel::reboot_and_continue`.
+const REBOOT_MARKER_KEY: &str = "rusty-charm-framework.reboot-continuation";
+
+/// A reboot requested by `EventModel::reboot_and_continue`.
+#[derive(Serialize, Deserialize)]
+struct RebootMarker {
+    token: String,
+    /// Boot ID at the time of the request, to tell whether the reboot has happened.
+    boot_id: String,
+}
+
 pub struct EventModel<'a, B> {
     backend: &'a B,
     pub event: Event,
+    /// Identity of this unit and its model.
+    pub info: ModelInfo,
     pub unit: Unit<'a, B>,
     pub relations: Relations<'a, B>,
     pub ports: PortManager<'a, B>,
@@ -274,32 +660,112 @@ impl<'a, B> EventModel<'a, B>
 where
     B: Backend,
 {
-    pub(crate) fn new(backend: &'a B, event: Event) -> Self {
+    pub(crate) fn new(backend: &'a B, event: Event, info: ModelInfo, log_level: LogLevel) -> Self {
         Self {
             event,
+            info,
             backend,
             unit: Unit::new(backend),
             ports: PortManager::new(backend),
             status: StatusManager::new(backend),
-            log: Logger::new(backend),
+            log: Logger::new(backend, log_level),
             relations: Relations::new(backend),
         }
     }
 
+    /// Get the peer relation on `endpoint`,
+    /// or `None` if the peer relation has not been created yet.
+    pub fn peers(&self, endpoint: &str) -> Result<Option<Peers<'a, B>>> {
+        Peers::new(self.backend, endpoint)
+    }
+
     // these can only be called in an event hook
 
+    /// Get the methods for reporting metrics.
+    /// These are only available during the collect-metrics hook;
+    /// at other times this returns `Error::MetricsUnavailable`.
+    pub fn metrics(&self) -> Result<Metrics<'a, B>> {
+        if !matches!(self.event, Event::CollectMetrics) {
+            return Err(Error::MetricsUnavailable);
+        }
+        Metrics::new(self.backend)
+    }
+
+    /// Get the helpers for upgrading the base of the machine.
+    /// These are only available during the pre-series-upgrade and post-series-upgrade hooks;
+    /// at other times this returns `Error::SeriesUpgradeUnavailable`.
+    pub fn series_upgrade(&self) -> Result<SeriesUpgrade<'a, B>> {
+        if !matches!(
+            self.event,
+            Event::PreSeriesUpgrade | Event::PostSeriesUpgrade
+        ) {
+            return Err(Error::SeriesUpgradeUnavailable);
+        }
+        Ok(SeriesUpgrade::new(self.backend))
+    }
+
+    /// Reboot the machine after the current hook finishes.
     pub fn reboot(&self) -> Result<()> {
         self.backend.reboot(false)
     }
 
+    /// Reboot the machine immediately.
+    /// The current hook is stopped and run again after the reboot,
+    /// so it must be safe to re-run.
     pub fn reboot_now(&self) -> Result<()> {
         self.backend.reboot(true)
     }
+
+    /// Reboot the machine after the current hook finishes,
+    /// recording `token` so the workflow that needed the reboot can continue afterwards.
+    /// Once the machine has restarted, `reboot_continuation` returns the token in the next hook.
+    ///
+    /// ```ignore
+    /// if let Some(token) = model.reboot_continuation()? {
+    ///     if token == "kernel-upgrade" {
+    ///         finish_kernel_upgrade()?;
+    ///     }
+    /// } else if needs_kernel_upgrade() {
+    ///     start_kernel_upgrade()?;
+    ///     model.reboot_and_continue("kernel-upgrade")?;
+    ///     return Ok(Status::Maintenance("rebooting for kernel upgrade"));
+    /// }
+    /// ```
+    pub fn reboot_and_continue(&self, token: &str) -> Result<()> {
+        let marker = RebootMarker {
+            token: token.to_owned(),
+            boot_id: self.backend.boot_id()?,
+        };
+        self.backend
+            .set_unit_state(REBOOT_MARKER_KEY, &serde_json::to_string(&marker)?)?;
+        self.backend.reboot(false)
+    }
+
+    /// If the machine has rebooted since `reboot_and_continue` was called,
+    /// return the token it was called with and clear it, so it is only returned once.
+    /// Returns `None` if no reboot was requested, or it hasn't happened yet.
+    pub fn reboot_continuation(&self) -> Result<Option<String>> {
+        let Some(marker) = self.backend.get_unit_state()?.remove(REBOOT_MARKER_KEY) else {
+            return Ok(None);
+        };
+        let marker: RebootMarker = serde_json::from_str(&marker)?;
+        if marker.boot_id == self.backend.boot_id()? {
+            return Ok(None);
+        }
+        self.backend.delete_unit_state(REBOOT_MARKER_KEY)?;
+        Ok(Some(marker.token))
+    }
 }
 
 pub struct ActionModel<'a, A, B> {
     backend: &'a B,
     pub action: A,
+    /// Unique ID of this action invocation.
+    pub id: String,
+    /// Identity of the unit the action is running on, and its model.
+    pub info: ModelInfo,
+    /// The action timeout, and the time it began counting from.
+    deadline: Option<(Instant, Duration)>,
     pub unit: Unit<'a, B>,
     pub ports: PortManager<'a, B>,
     /// Contains methods to update the unit status.
@@ -312,23 +778,72 @@ impl<'a, A, B> ActionModel<'a, A, B>
 where
     B: Backend,
 {
-    pub(crate) fn new(backend: &'a B, action: A) -> Self {
+    pub(crate) fn new(
+        backend: &'a B,
+        action: A,
+        id: String,
+        info: ModelInfo,
+        timeout: Option<Duration>,
+        log_level: LogLevel,
+    ) -> Self {
         Self {
             action,
+            id,
+            info,
+            deadline: timeout.map(|timeout| (Instant::now(), timeout)),
             backend,
             unit: Unit::new(backend),
             ports: PortManager::new(backend),
             status: StatusManager::new(backend),
-            log: Logger::new(backend),
+            log: Logger::new(backend, log_level),
             relations: Relations::new(backend),
         }
     }
 
+    /// Get the peer relation on `endpoint`,
+    /// or `None` if the peer relation has not been created yet.
+    pub fn peers(&self, endpoint: &str) -> Result<Option<Peers<'a, B>>> {
+        Peers::new(self.backend, endpoint)
+    }
+
     // these can only be called in an action
 
     pub fn action_log(&self, msg: &str) -> Result<()> {
         self.backend.action_log(msg)
     }
+
+    /// Log a progress update to the action log, in the format `[ 40%] (2/5) msg`.
+    /// Also acts as a cancellation point:
+    /// if the action has exceeded its deadline, nothing is logged and an error is returned.
+    /// When propagated from the handler, this error fails the action with a clear message.
+    pub fn progress(&self, done: u64, total: u64, msg: &str) -> Result<()> {
+        self.check_deadline()?;
+        // u128 so large totals can't overflow
+        let percent = (u128::from(done.min(total)) * 100)
+            .checked_div(u128::from(total))
+            .unwrap_or(100);
+        self.backend
+            .action_log(&format!("[{percent:>3}%] ({done}/{total}) {msg}"))
+    }
+
+    /// Time remaining before the action exceeds its deadline.
+    /// Returns `None` if no timeout was configured for actions.
+    pub fn time_remaining(&self) -> Option<Duration> {
+        self.deadline
+            .map(|(start, timeout)| timeout.saturating_sub(start.elapsed()))
+    }
+
+    /// Return an error if the action has exceeded its deadline.
+    /// Long running handlers should call this (or `progress`) regularly,
+    /// and propagate the error to stop work cleanly.
+    pub fn check_deadline(&self) -> Result<()> {
+        match self.deadline {
+            Some((start, timeout)) if start.elapsed() > timeout => {
+                Err(Error::ActionDeadlineExceeded(timeout))
+            }
+            _ => Ok(()),
+        }
+    }
 }
 
 pub struct LeaderTools<'a, B> {
@@ -354,6 +869,16 @@ where
     pub fn relation_set_app(&self, app: &RelatedApp, key: &str, value: &str) -> Result<()> {
         self.backend.relation_set_app(app, key, value)
     }
+
+    /// Set several keys in the application databag of the relation with `app` at once.
+    /// Keys with a `None` value are deleted.
+    pub fn relation_set_app_many(
+        &self,
+        app: &RelatedApp,
+        data: &HashMap<String, Option<String>>,
+    ) -> Result<()> {
+        self.backend.relation_set_many(app, data, true)
+    }
 }
 
 pub struct AppStatus<'a, B> {
@@ -401,6 +926,26 @@ where
         self.backend.related_apps(endpoint)
     }
 
+    /// Get the related app for the relation with id `relation_id` (eg. `database:3`).
+    pub fn get(&self, relation_id: &str) -> Result<RelatedApp> {
+        self.backend.related_app(relation_id)
+    }
+
+    /// Get the related app for the relation the current relation event is for.
+    /// Returns `None` if the current event is not a relation event.
+    pub fn current(&self) -> Result<Option<RelatedApp>> {
+        match self.backend.relation_id()? {
+            Some(relation_id) => Ok(Some(self.backend.related_app(&relation_id)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the UUID of the model the related app is deployed in.
+    /// This differs from the local model's UUID for cross-model relations.
+    pub fn model_uuid(&self, app: &RelatedApp) -> Result<String> {
+        self.backend.relation_model_uuid(app)
+    }
+
     pub fn app_settings(&self, app: &RelatedApp) -> Result<HashMap<String, String>> {
         self.backend.relation_get_app(app)
     }
@@ -409,7 +954,354 @@ where
         self.backend.relation_get_unit(unit)
     }
 
+    /// Read back the data this application has set on the relation with `app`.
+    /// Except for peer relations, only the leader can read this.
+    pub fn local_app_settings(&self, app: &RelatedApp) -> Result<HashMap<String, String>> {
+        self.backend.relation_get_local_app(app)
+    }
+
+    /// Read back the data this unit has set on the relation with `app`.
+    /// Compare against this before writing,
+    /// to avoid triggering relation-changed events on the remote side for no change.
+    pub fn local_unit_settings(&self, app: &RelatedApp) -> Result<HashMap<String, String>> {
+        self.backend.relation_get_local_unit(app)
+    }
+
     pub fn set_unit(&self, app: &RelatedApp, key: &str, value: &str) -> Result<()> {
         self.backend.relation_set_unit(app, key, value)
     }
+
+    /// Set several keys in this unit's databag of the relation with `app` at once.
+    /// Keys with a `None` value are deleted.
+    /// This only triggers one relation-changed event on the remote side.
+    pub fn set_unit_many(
+        &self,
+        app: &RelatedApp,
+        data: &HashMap<String, Option<String>>,
+    ) -> Result<()> {
+        self.backend.relation_set_many(app, data, false)
+    }
+}
+
+/// Methods for working with a peer relation,
+/// used to coordinate between the units of this application.
+///
+/// ```ignore
+/// if let Some(peers) = model.peers("others")? {
+///     peers.set_unit_data("ready", "true")?;
+///     if peers.all_peers_have("ready")? {
+///         bootstrap_cluster();
+///     }
+/// }
+/// ```
+pub struct Peers<'a, B> {
+    backend: &'a B,
+    relation: RelatedApp,
+}
+
+impl<'a, B> Peers<'a, B>
+where
+    B: Backend,
+{
+    fn new(backend: &'a B, endpoint: &str) -> Result<Option<Self>> {
+        // A peer relation is a relation with this application,
+        // so there is at most one relation on the endpoint.
+        let Some(relation) = backend.related_apps(endpoint)?.into_iter().next() else {
+            return Ok(None);
+        };
+        Ok(Some(Self { backend, relation }))
+    }
+
+    /// The peer relation itself, for use with the `Relations` methods.
+    pub fn relation(&self) -> &RelatedApp {
+        &self.relation
+    }
+
+    /// The other units of this application.
+    /// This unit is not included.
+    pub fn units(&self) -> &[RelatedUnit] {
+        &self.relation.units
+    }
+
+    /// Read the application databag, shared by all units of this application.
+    pub fn app_data(&self) -> Result<HashMap<String, String>> {
+        self.backend.relation_get_local_app(&self.relation)
+    }
+
+    /// Set a key in the application databag.
+    /// Only the leader can write to the application databag;
+    /// returns `Error::NotLeader` if this unit is not the leader.
+    pub fn set_app_data(&self, key: &str, value: &str) -> Result<()> {
+        if !self.backend.is_leader()? {
+            return Err(Error::NotLeader);
+        }
+        self.backend.relation_set_app(&self.relation, key, value)
+    }
+
+    /// Read this unit's own databag.
+    pub fn unit_data(&self) -> Result<HashMap<String, String>> {
+        self.backend.relation_get_local_unit(&self.relation)
+    }
+
+    /// Set a key in this unit's own databag, to be read by the other units.
+    pub fn set_unit_data(&self, key: &str, value: &str) -> Result<()> {
+        self.backend.relation_set_unit(&self.relation, key, value)
+    }
+
+    /// Read the databag of another unit of this application.
+    pub fn peer_data(&self, unit: &RelatedUnit) -> Result<HashMap<String, String>> {
+        self.backend.relation_get_unit(unit)
+    }
+
+    /// True if every other unit has published a non-empty value for `key` in its databag.
+    /// This unit is not checked.
+    pub fn all_peers_have(&self, key: &str) -> Result<bool> {
+        for unit in self.units() {
+            if self
+                .peer_data(unit)?
+                .get(key)
+                .is_none_or(|value| value.is_empty())
+            {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Methods for reporting metrics with the `add-metric` hook tool,
+/// validated against the metrics defined in the charm's `metrics.yaml`.
+///
+/// ```ignore
+/// if let Event::CollectMetrics = model.event {
+///     let metrics = model.metrics()?;
+///     metrics.add("active-users", MetricValue::Gauge(count_users() as f64))?;
+/// }
+/// ```
+pub struct Metrics<'a, B> {
+    backend: &'a B,
+    definitions: HashMap<String, MetricDefinition>,
+}
+
+impl<'a, B> Metrics<'a, B>
+where
+    B: Backend,
+{
+    fn new(backend: &'a B) -> Result<Self> {
+        Ok(Self {
+            backend,
+            definitions: backend.metric_definitions()?,
+        })
+    }
+
+    /// The metrics defined in the charm's `metrics.yaml`, keyed by name.
+    pub fn definitions(&self) -> &HashMap<String, MetricDefinition> {
+        &self.definitions
+    }
+
+    /// Record a value for the metric `name`.
+    pub fn add(&self, name: &str, value: MetricValue) -> Result<()> {
+        self.add_with_labels(name, value, &HashMap::new())
+    }
+
+    /// Record a value for the metric `name`, with labels to distinguish it from other values for
+    /// the same metric.
+    /// Returns `Error::InvalidMetric` without calling the hook tool if the metric is not defined
+    /// in `metrics.yaml`, the value doesn't match the metric type, or the labels are invalid.
+    pub fn add_with_labels(
+        &self,
+        name: &str,
+        value: MetricValue,
+        labels: &HashMap<String, String>,
+    ) -> Result<()> {
+        let definition = self.definitions.get(name).ok_or_else(|| {
+            Error::InvalidMetric(format!("{name} is not defined in metrics.yaml"))
+        })?;
+        match definition.kind {
+            None => {
+                return Err(Error::InvalidMetric(format!(
+                    "{name} is built in to juju and can't be added by the charm"
+                )))
+            }
+            Some(kind) if kind != value.kind() => {
+                return Err(Error::InvalidMetric(format!(
+                    "{name} is defined as {kind:?}, but a {:?} value was given",
+                    value.kind()
+                )))
+            }
+            Some(_) => {}
+        }
+        if !value.value().is_finite() {
+            return Err(Error::InvalidMetric(format!(
+                "{name} must be a finite number"
+            )));
+        }
+        if value.kind() == MetricKind::Absolute && value.value() < 0.0 {
+            return Err(Error::InvalidMetric(format!(
+                "{name} is an absolute metric, so can't be negative"
+            )));
+        }
+        for (key, label) in labels {
+            if [key, label]
+                .iter()
+                .any(|s| s.is_empty() || s.contains([',', '=']))
+            {
+                return Err(Error::InvalidMetric(format!(
+                    "label {key:?}={label:?} for {name}: \
+                     labels and their values must be non-empty, without ',' or '='"
+                )));
+            }
+        }
+
+        self.backend.add_metric(name, value.value(), labels)
+    }
+}
+
+/// Unit state key for the progress of a series upgrade.
+const SERIES_UPGRADE_STATE_KEY: &str = "rusty-charm-framework.series-upgrade-state";
+
+/// Unit state key for the base a series upgrade is targeting.
+const SERIES_UPGRADE_TARGET_KEY: &str = "rusty-charm-framework.series-upgrade-target";
+
+/// Progress of a series upgrade, as recorded in unit state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeriesUpgradeState {
+    /// The workload has been paused in pre-series-upgrade,
+    /// and the machine is ready to be upgraded.
+    Prepared,
+    /// The workload has been resumed and verified in post-series-upgrade.
+    Completed,
+}
+
+impl SeriesUpgradeState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SeriesUpgradeState::Prepared => "prepared",
+            SeriesUpgradeState::Completed => "completed",
+        }
+    }
+}
+
+/// Helpers for the standard workflow when upgrading the base of the machine a unit is on:
+/// the workload is paused in pre-series-upgrade,
+/// and resumed and verified in post-series-upgrade, after the operator has upgraded the machine.
+/// The progress of the workflow is recorded in unit state.
+///
+/// ```ignore
+/// match model.event {
+///     Event::PreSeriesUpgrade => {
+///         return model.series_upgrade()?.prepare(|| stop_service("myapp"));
+///     }
+///     Event::PostSeriesUpgrade => {
+///         model.series_upgrade()?.complete(|| start_service("myapp"), check_health)?;
+///     }
+///     ...
+/// }
+/// ```
+pub struct SeriesUpgrade<'a, B> {
+    backend: &'a B,
+    status: StatusManager<'a, B>,
+}
+
+impl<'a, B> SeriesUpgrade<'a, B>
+where
+    B: Backend,
+{
+    fn new(backend: &'a B) -> Self {
+        Self {
+            backend,
+            status: StatusManager::new(backend),
+        }
+    }
+
+    /// Get the base the machine is being upgraded to.
+    /// This is read from the environment set by juju,
+    /// falling back to the target recorded by `prepare` until `complete` clears it.
+    pub fn target(&self) -> Result<Option<Base>> {
+        if let Some(base) = self.backend.target_base()? {
+            return Ok(Some(base));
+        }
+        match self
+            .backend
+            .get_unit_state()?
+            .remove(SERIES_UPGRADE_TARGET_KEY)
+        {
+            Some(base) => Ok(Some(base.parse()?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the progress of the current or most recent series upgrade,
+    /// or `None` if this unit has not been through one.
+    pub fn state(&self) -> Result<Option<SeriesUpgradeState>> {
+        let state = self
+            .backend
+            .get_unit_state()?
+            .remove(SERIES_UPGRADE_STATE_KEY);
+        Ok(match state.as_deref() {
+            Some("prepared") => Some(SeriesUpgradeState::Prepared),
+            Some("completed") => Some(SeriesUpgradeState::Completed),
+            _ => None,
+        })
+    }
+
+    /// Prepare for the upgrade in the pre-series-upgrade hook:
+    /// set a maintenance status, run `pause` to stop the workload,
+    /// and record the upgrade as prepared.
+    /// Returns the status for the event handler to return,
+    /// which tells the operator the machine is ready to upgrade.
+    pub fn prepare<F>(&self, pause: F) -> anyhow::Result<Status<'static>>
+    where
+        F: FnOnce() -> anyhow::Result<()>,
+    {
+        let target = self.target()?;
+        let msg = match &target {
+            Some(base) => format!("preparing for series upgrade to {base}"),
+            None => "preparing for series upgrade".to_owned(),
+        };
+        self.status.maintenance(&msg)?;
+        pause()?;
+
+        if let Some(base) = target {
+            self.backend
+                .set_unit_state(SERIES_UPGRADE_TARGET_KEY, &base.to_string())?;
+        }
+        self.record(SeriesUpgradeState::Prepared)?;
+        Ok(Status::Maintenance("ready for series upgrade"))
+    }
+
+    /// Finish the upgrade in the post-series-upgrade hook:
+    /// set a maintenance status, run `resume` to start the workload again,
+    /// then `verify` to check it is working on the new base,
+    /// and record the upgrade as completed.
+    /// If either function fails, the error is returned and the upgrade stays prepared,
+    /// so the hook can be retried.
+    pub fn complete<R, V>(&self, resume: R, verify: V) -> anyhow::Result<()>
+    where
+        R: FnOnce() -> anyhow::Result<()>,
+        V: FnOnce() -> anyhow::Result<()>,
+    {
+        if self.state()? != Some(SeriesUpgradeState::Prepared) {
+            self.backend.log(
+                "completing a series upgrade that was not prepared by this charm",
+                LogLevel::Warning,
+            )?;
+        }
+        let msg = match self.target()? {
+            Some(base) => format!("completing series upgrade to {base}"),
+            None => "completing series upgrade".to_owned(),
+        };
+        self.status.maintenance(&msg)?;
+        resume()?;
+        verify()?;
+        self.record(SeriesUpgradeState::Completed)?;
+        // so a later upgrade without a target from juju doesn't reuse this one
+        self.backend.delete_unit_state(SERIES_UPGRADE_TARGET_KEY)?;
+        Ok(())
+    }
+
+    fn record(&self, state: SeriesUpgradeState) -> Result<()> {
+        self.backend
+            .set_unit_state(SERIES_UPGRADE_STATE_KEY, state.as_str())
+    }
 }
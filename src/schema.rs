@@ -0,0 +1,500 @@
+// Minimal JSON-schema validation, covering the subset of keywords that juju supports for action
+// parameters in `actions.yaml`.
+// ref. https://juju.is/docs/sdk/actions-yaml
+use serde_json::{Map, Value};
+
+/// Build the JSON-schema for the parameters of `action_name` from the parsed contents of
+/// `actions.yaml`.
+/// Returns `None` if the action is not defined.
+///
+/// Juju does not require a top level schema for the action parameters;
+/// instead the `params`, `required`, and `additionalProperties` keys of the action define it.
+/// If `additionalProperties` is not set, additional parameters are allowed.
+pub fn action_schema(actions: &Value, action_name: &str) -> Option<Value> {
+    let action = actions.get(action_name)?;
+
+    let mut schema = Map::new();
+    schema.insert("type".to_owned(), Value::String("object".to_owned()));
+    schema.insert(
+        "properties".to_owned(),
+        action
+            .get("params")
+            .cloned()
+            .unwrap_or(Value::Object(Map::new())),
+    );
+    if let Some(required) = action.get("required") {
+        schema.insert("required".to_owned(), required.clone());
+    }
+    if let Some(additional) = action.get("additionalProperties") {
+        schema.insert("additionalProperties".to_owned(), additional.clone());
+    }
+    Some(Value::Object(schema))
+}
+
+/// Validate `value` against `schema`.
+/// Returns a list of human readable messages, one for each violation found.
+/// An empty list means the value is valid.
+///
+/// Supported keywords:
+/// `type`, `enum`, `const`, `minimum`, `maximum`, `exclusiveMinimum`, `exclusiveMaximum`,
+/// `minLength`, `maxLength`, `pattern`, `properties`, `required`, `additionalProperties`,
+/// `items`, `minItems`, and `maxItems`.
+/// Unknown keywords are ignored.
+pub fn validate(schema: &Value, value: &Value) -> Vec<String> {
+    let mut violations = vec![];
+    validate_at(schema, value, "", &mut violations);
+    violations
+}
+
+fn validate_at(schema: &Value, value: &Value, path: &str, violations: &mut Vec<String>) {
+    let schema = match schema {
+        Value::Object(schema) => schema,
+        // `true` or a missing schema accepts anything
+        Value::Bool(false) => {
+            violations.push(format!("{}: is not allowed", display_path(path)));
+            return;
+        }
+        _ => return,
+    };
+
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => vec![],
+        };
+        if !types.is_empty() && !types.iter().any(|name| is_type(value, name)) {
+            violations.push(format!(
+                "{}: expected {}, got {}",
+                display_path(path),
+                types.join(" or "),
+                type_name(value),
+            ));
+            // further checks are meaningless if the type is wrong
+            return;
+        }
+    }
+
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            violations.push(format!(
+                "{}: {} is not one of {}",
+                display_path(path),
+                value,
+                Value::Array(allowed.clone()),
+            ));
+        }
+    }
+
+    if let Some(expected) = schema.get("const") {
+        if expected != value {
+            violations.push(format!(
+                "{}: expected {}, got {}",
+                display_path(path),
+                expected,
+                value
+            ));
+        }
+    }
+
+    match value {
+        Value::Number(number) => {
+            if let Some(number) = number.as_f64() {
+                validate_number(schema, number, path, violations);
+            }
+        }
+        Value::String(string) => validate_string(schema, string, path, violations),
+        Value::Array(items) => validate_array(schema, items, path, violations),
+        Value::Object(object) => validate_object(schema, object, path, violations),
+        _ => {}
+    }
+}
+
+fn validate_number(
+    schema: &Map<String, Value>,
+    number: f64,
+    path: &str,
+    violations: &mut Vec<String>,
+) {
+    // draft 4 uses booleans for exclusiveMinimum/exclusiveMaximum,
+    // later drafts use the limit itself.
+    let exclusive_min = schema.get("exclusiveMinimum");
+    let exclusive_max = schema.get("exclusiveMaximum");
+
+    if let Some(minimum) = schema.get("minimum").and_then(Value::as_f64) {
+        if exclusive_min == Some(&Value::Bool(true)) {
+            if number <= minimum {
+                violations.push(format!(
+                    "{}: must be greater than {minimum}, got {number}",
+                    display_path(path)
+                ));
+            }
+        } else if number < minimum {
+            violations.push(format!(
+                "{}: must be at least {minimum}, got {number}",
+                display_path(path)
+            ));
+        }
+    }
+    if let Some(minimum) = exclusive_min.and_then(Value::as_f64) {
+        if number <= minimum {
+            violations.push(format!(
+                "{}: must be greater than {minimum}, got {number}",
+                display_path(path)
+            ));
+        }
+    }
+
+    if let Some(maximum) = schema.get("maximum").and_then(Value::as_f64) {
+        if exclusive_max == Some(&Value::Bool(true)) {
+            if number >= maximum {
+                violations.push(format!(
+                    "{}: must be less than {maximum}, got {number}",
+                    display_path(path)
+                ));
+            }
+        } else if number > maximum {
+            violations.push(format!(
+                "{}: must be at most {maximum}, got {number}",
+                display_path(path)
+            ));
+        }
+    }
+    if let Some(maximum) = exclusive_max.and_then(Value::as_f64) {
+        if number >= maximum {
+            violations.push(format!(
+                "{}: must be less than {maximum}, got {number}",
+                display_path(path)
+            ));
+        }
+    }
+}
+
+fn validate_string(
+    schema: &Map<String, Value>,
+    string: &str,
+    path: &str,
+    violations: &mut Vec<String>,
+) {
+    let length = string.chars().count() as u64;
+    if let Some(min_length) = schema.get("minLength").and_then(Value::as_u64) {
+        if length < min_length {
+            violations.push(format!(
+                "{}: must be at least {min_length} characters long",
+                display_path(path)
+            ));
+        }
+    }
+    if let Some(max_length) = schema.get("maxLength").and_then(Value::as_u64) {
+        if length > max_length {
+            violations.push(format!(
+                "{}: must be at most {max_length} characters long",
+                display_path(path)
+            ));
+        }
+    }
+    if let Some(pattern) = schema.get("pattern").and_then(Value::as_str) {
+        match regex::Regex::new(pattern) {
+            Ok(re) => {
+                if !re.is_match(string) {
+                    violations.push(format!(
+                        "{}: {string:?} does not match pattern {pattern:?}",
+                        display_path(path)
+                    ));
+                }
+            }
+            Err(e) => {
+                violations.push(format!(
+                    "{}: schema contains an invalid pattern {pattern:?}: {e}",
+                    display_path(path)
+                ));
+            }
+        }
+    }
+}
+
+fn validate_array(
+    schema: &Map<String, Value>,
+    items: &[Value],
+    path: &str,
+    violations: &mut Vec<String>,
+) {
+    let count = items.len() as u64;
+    if let Some(min_items) = schema.get("minItems").and_then(Value::as_u64) {
+        if count < min_items {
+            violations.push(format!(
+                "{}: must contain at least {min_items} items",
+                display_path(path)
+            ));
+        }
+    }
+    if let Some(max_items) = schema.get("maxItems").and_then(Value::as_u64) {
+        if count > max_items {
+            violations.push(format!(
+                "{}: must contain at most {max_items} items",
+                display_path(path)
+            ));
+        }
+    }
+    if let Some(item_schema) = schema.get("items") {
+        for (i, item) in items.iter().enumerate() {
+            validate_at(item_schema, item, &format!("{path}[{i}]"), violations);
+        }
+    }
+}
+
+fn validate_object(
+    schema: &Map<String, Value>,
+    object: &Map<String, Value>,
+    path: &str,
+    violations: &mut Vec<String>,
+) {
+    let empty = Map::new();
+    let properties = schema
+        .get("properties")
+        .and_then(Value::as_object)
+        .unwrap_or(&empty);
+
+    if let Some(Value::Array(required)) = schema.get("required") {
+        for key in required.iter().filter_map(Value::as_str) {
+            if !object.contains_key(key) {
+                violations.push(format!(
+                    "{}: is required but was not provided",
+                    display_path(&join_path(path, key))
+                ));
+            }
+        }
+    }
+
+    for (key, value) in object {
+        let key_path = join_path(path, key);
+        if let Some(property_schema) = properties.get(key) {
+            validate_at(property_schema, value, &key_path, violations);
+            continue;
+        }
+        match schema.get("additionalProperties") {
+            Some(Value::Bool(false)) => {
+                violations.push(format!(
+                    "{}: is not a known parameter",
+                    display_path(&key_path)
+                ));
+            }
+            Some(additional @ Value::Object(_)) => {
+                validate_at(additional, value, &key_path, violations);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn is_type(value: &Value, name: &str) -> bool {
+    match name {
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "null" => value.is_null(),
+        "number" => value.is_number(),
+        "integer" => match value {
+            Value::Number(number) => {
+                number.is_i64()
+                    || number.is_u64()
+                    || number.as_f64().is_some_and(|n| n.fract() == 0.0)
+            }
+            _ => false,
+        },
+        // unknown type names can't be checked
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(number) if number.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn join_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{path}.{key}")
+    }
+}
+
+fn display_path(path: &str) -> &str {
+    if path.is_empty() {
+        "parameters"
+    } else {
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn action_schema_from_actions_yaml() {
+        let actions = json!({
+            "backup": {
+                "params": {"target": {"type": "string"}},
+                "required": ["target"],
+                "additionalProperties": false,
+            },
+        });
+        assert_eq!(
+            action_schema(&actions, "backup"),
+            Some(json!({
+                "type": "object",
+                "properties": {"target": {"type": "string"}},
+                "required": ["target"],
+                "additionalProperties": false,
+            }))
+        );
+        assert_eq!(action_schema(&actions, "restore"), None);
+    }
+
+    #[test]
+    fn valid_value_has_no_violations() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "count": {"type": "integer", "minimum": 1},
+                "name": {"type": "string", "pattern": "^[a-z]+$"},
+            },
+            "required": ["count"],
+        });
+        assert!(validate(&schema, &json!({"count": 3, "name": "abc"})).is_empty());
+    }
+
+    #[test]
+    fn wrong_type_stops_further_checks() {
+        let schema = json!({"type": "string", "minLength": 5});
+        assert_eq!(
+            validate(&schema, &json!(3)),
+            vec!["parameters: expected string, got integer"]
+        );
+    }
+
+    #[test]
+    fn integer_accepts_whole_floats() {
+        let schema = json!({"type": "integer"});
+        assert!(validate(&schema, &json!(2.0)).is_empty());
+        assert_eq!(
+            validate(&schema, &json!(2.5)),
+            vec!["parameters: expected integer, got number"]
+        );
+    }
+
+    #[test]
+    fn exclusive_bounds_as_booleans_and_numbers() {
+        // draft 4
+        let schema = json!({"minimum": 1, "exclusiveMinimum": true});
+        assert_eq!(validate(&schema, &json!(1)).len(), 1);
+        assert!(validate(&schema, &json!(2)).is_empty());
+
+        // draft 6 and later
+        let schema = json!({"exclusiveMaximum": 10});
+        assert_eq!(
+            validate(&schema, &json!(10)),
+            vec!["parameters: must be less than 10, got 10"]
+        );
+        assert!(validate(&schema, &json!(9)).is_empty());
+
+        let schema = json!({"minimum": 1, "maximum": 10});
+        assert!(validate(&schema, &json!(1)).is_empty());
+        assert!(validate(&schema, &json!(10)).is_empty());
+        assert_eq!(validate(&schema, &json!(11)).len(), 1);
+    }
+
+    #[test]
+    fn string_constraints() {
+        let schema = json!({"minLength": 2, "maxLength": 3, "pattern": "^a"});
+        assert!(validate(&schema, &json!("ab")).is_empty());
+        assert_eq!(validate(&schema, &json!("b")).len(), 2);
+        assert_eq!(
+            validate(&json!({"pattern": "("}), &json!("a"))
+                .first()
+                .map(|msg| msg.contains("invalid pattern")),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn enum_and_const() {
+        assert!(validate(&json!({"enum": ["a", "b"]}), &json!("a")).is_empty());
+        assert_eq!(validate(&json!({"enum": ["a", "b"]}), &json!("c")).len(), 1);
+        assert_eq!(
+            validate(&json!({"const": 1}), &json!(2)),
+            vec!["parameters: expected 1, got 2"]
+        );
+    }
+
+    #[test]
+    fn nested_paths_in_messages() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "servers": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {"port": {"type": "integer"}},
+                        "required": ["host"],
+                    },
+                },
+            },
+        });
+        let value = json!({"servers": [{"host": "a", "port": 1}, {"port": "x"}]});
+        assert_eq!(
+            validate(&schema, &value),
+            vec![
+                "servers[1].host: is required but was not provided",
+                "servers[1].port: expected integer, got string",
+            ]
+        );
+    }
+
+    #[test]
+    fn additional_properties() {
+        let schema = json!({"properties": {"a": {}}, "additionalProperties": false});
+        assert_eq!(
+            validate(&schema, &json!({"a": 1, "b": 2})),
+            vec!["b: is not a known parameter"]
+        );
+
+        let schema = json!({"additionalProperties": {"type": "string"}});
+        assert_eq!(
+            validate(&schema, &json!({"b": 2})),
+            vec!["b: expected string, got integer"]
+        );
+
+        // allowed by default
+        assert!(validate(&json!({"properties": {}}), &json!({"b": 2})).is_empty());
+    }
+
+    #[test]
+    fn array_length() {
+        let schema = json!({"minItems": 1, "maxItems": 2});
+        assert_eq!(validate(&schema, &json!([])).len(), 1);
+        assert!(validate(&schema, &json!([1, 2])).is_empty());
+        assert_eq!(validate(&schema, &json!([1, 2, 3])).len(), 1);
+    }
+
+    #[test]
+    fn false_schema_rejects_everything() {
+        assert_eq!(
+            validate(&json!(false), &json!(1)),
+            vec!["parameters: is not allowed"]
+        );
+        assert!(validate(&json!(true), &json!(1)).is_empty());
+    }
+}
@@ -0,0 +1,370 @@
+// An in-memory backend for unit tests, counting the hook tools called.
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Mutex, MutexGuard},
+};
+
+use serde_json::Value;
+
+use crate::backend::Backend;
+use crate::error::{Error, Result};
+use crate::model::{GoalState, ModelInfo};
+use crate::types::{
+    ActionResultKey, ActionValue, Base, JujuCredentials, JujuVersion, LogLevel, MetricDefinition,
+    OpenedPort, Payload, PayloadStatus, Port, RelatedApp, RelatedUnit, Status,
+};
+
+#[derive(Default)]
+pub(crate) struct FakeState {
+    pub hook_name: String,
+    pub is_leader: bool,
+    pub config: Value,
+    pub unit_state: HashMap<String, String>,
+    pub leader_data: HashMap<String, String>,
+    pub opened_ports: Vec<OpenedPort>,
+    /// Relation data, keyed by relation id then the app or unit name that owns it.
+    pub databags: HashMap<String, HashMap<String, HashMap<String, String>>>,
+    /// The last status set, as `name: message`.
+    pub status: Option<String>,
+}
+
+/// Unit `app/0`, with state held in memory.
+/// `calls` counts the calls to each method that would run a hook tool.
+#[derive(Default)]
+pub(crate) struct FakeBackend {
+    pub state: Mutex<FakeState>,
+    calls: Mutex<HashMap<&'static str, usize>>,
+}
+
+impl FakeBackend {
+    pub fn new(state: FakeState) -> Self {
+        Self {
+            state: Mutex::new(state),
+            calls: Mutex::default(),
+        }
+    }
+
+    pub fn state(&self) -> MutexGuard<'_, FakeState> {
+        self.state.lock().unwrap()
+    }
+
+    /// How many times `method` has been called.
+    pub fn calls(&self, method: &str) -> usize {
+        self.calls
+            .lock()
+            .unwrap()
+            .get(method)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn count(&self, method: &'static str) {
+        *self.calls.lock().unwrap().entry(method).or_default() += 1;
+    }
+
+    fn call(&self, method: &'static str) -> MutexGuard<'_, FakeState> {
+        self.count(method);
+        self.state()
+    }
+
+    fn databag(
+        &self,
+        method: &'static str,
+        relation_id: &str,
+        owner: &str,
+    ) -> HashMap<String, String> {
+        self.call(method)
+            .databags
+            .get(relation_id)
+            .and_then(|databags| databags.get(owner))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn set_databag(
+        &self,
+        method: &'static str,
+        relation_id: &str,
+        owner: &str,
+        key: &str,
+        value: &str,
+    ) {
+        self.call(method)
+            .databags
+            .entry(relation_id.to_owned())
+            .or_default()
+            .entry(owner.to_owned())
+            .or_default()
+            .insert(key.to_owned(), value.to_owned());
+    }
+}
+
+/// A related app on relation `relation_id`, with no units.
+pub(crate) fn related_app(name: &str, endpoint: &str, relation_id: &str) -> RelatedApp {
+    RelatedApp {
+        name: name.to_owned(),
+        units: vec![],
+        endpoint: endpoint.to_owned(),
+        relation_id: relation_id.to_owned(),
+    }
+}
+
+impl Backend for FakeBackend {
+    fn leader_get(&self) -> Result<HashMap<String, String>> {
+        Ok(self.call("leader_get").leader_data.clone())
+    }
+
+    fn leader_set(&self, key: &str, value: &str) -> Result<()> {
+        self.call("leader_set")
+            .leader_data
+            .insert(key.to_owned(), value.to_owned());
+        Ok(())
+    }
+
+    fn credentials(&self) -> Result<JujuCredentials> {
+        Err(Error::HookToolError {
+            tool: "credential-get".to_owned(),
+            stderr: "not available in tests".to_owned(),
+        })
+    }
+
+    fn reboot(&self, _now: bool) -> Result<()> {
+        self.count("reboot");
+        Ok(())
+    }
+
+    fn boot_id(&self) -> Result<String> {
+        Ok("boot".to_owned())
+    }
+
+    fn set_application_version(&self, _version: &str) -> Result<()> {
+        self.count("set_application_version");
+        Ok(())
+    }
+
+    fn metric_definitions(&self) -> Result<HashMap<String, MetricDefinition>> {
+        Ok(HashMap::new())
+    }
+
+    fn add_metric(
+        &self,
+        _name: &str,
+        _value: f64,
+        _labels: &HashMap<String, String>,
+    ) -> Result<()> {
+        self.count("add_metric");
+        Ok(())
+    }
+
+    fn set_action_fail(&self, _msg: &str) -> Result<()> {
+        self.count("set_action_fail");
+        Ok(())
+    }
+
+    fn set_action_result(&self, _data: HashMap<ActionResultKey, ActionValue>) -> Result<()> {
+        self.count("set_action_result");
+        Ok(())
+    }
+
+    fn action_name(&self) -> Result<String> {
+        Ok(String::new())
+    }
+
+    fn action_id(&self) -> Result<String> {
+        Ok(String::new())
+    }
+
+    fn hook_name(&self) -> Result<String> {
+        Ok(self.state().hook_name.clone())
+    }
+
+    fn unit_name(&self) -> Result<String> {
+        Ok("app/0".to_owned())
+    }
+
+    fn model_info(&self) -> Result<ModelInfo> {
+        Ok(ModelInfo {
+            unit_name: "app/0".to_owned(),
+            app_name: "app".to_owned(),
+            principal_unit: None,
+            model_name: "test".to_owned(),
+            model_uuid: "00000000-0000-0000-0000-000000000000".to_owned(),
+            machine_id: Some("0".to_owned()),
+            availability_zone: None,
+            charm_dir: PathBuf::from("/var/lib/juju/agents/unit-app-0/charm"),
+            juju_version: Some(JujuVersion::new(3, 6, 0)),
+        })
+    }
+
+    fn log(&self, _msg: &str, _level: LogLevel) -> Result<()> {
+        Ok(())
+    }
+
+    fn action_params(&self) -> Result<Value> {
+        Ok(Value::Null)
+    }
+
+    fn action_schema(&self, _name: &str) -> Result<Option<Value>> {
+        Ok(None)
+    }
+
+    fn config<C>(&self) -> Result<C>
+    where
+        C: serde::de::DeserializeOwned,
+    {
+        Ok(serde_json::from_value(self.call("config").config.clone())?)
+    }
+
+    fn set_status(&self, status: Status) -> Result<()> {
+        self.call("set_status").status = Some(format!("{}: {}", status.name(), status.msg()));
+        Ok(())
+    }
+
+    fn set_app_status(&self, _status: Status) -> Result<()> {
+        self.count("set_app_status");
+        Ok(())
+    }
+
+    fn action_log(&self, _msg: &str) -> Result<()> {
+        self.count("action_log");
+        Ok(())
+    }
+
+    fn is_leader(&self) -> Result<bool> {
+        Ok(self.call("is_leader").is_leader)
+    }
+
+    fn payload_register(&self, _payload: &Payload) -> Result<()> {
+        self.count("payload_register");
+        Ok(())
+    }
+
+    fn payload_unregister(&self, _class: &str, _id: &str) -> Result<()> {
+        self.count("payload_unregister");
+        Ok(())
+    }
+
+    fn payload_status_set(&self, _class: &str, _id: &str, _status: PayloadStatus) -> Result<()> {
+        self.count("payload_status_set");
+        Ok(())
+    }
+
+    fn goal_state(&self) -> Result<GoalState> {
+        self.count("goal_state");
+        Ok(GoalState {
+            units: HashMap::new(),
+            relations: HashMap::new(),
+        })
+    }
+
+    fn opened_ports(&self) -> Result<Vec<OpenedPort>> {
+        Ok(self.call("opened_ports").opened_ports.clone())
+    }
+
+    fn open_port(&self, port: &Port, endpoints: Vec<&str>) -> Result<()> {
+        self.call("open_port").opened_ports.push(OpenedPort {
+            port: *port,
+            endpoints: endpoints.into_iter().map(str::to_owned).collect(),
+        });
+        Ok(())
+    }
+
+    fn close_port(&self, port: &Port, _endpoints: Vec<&str>) -> Result<()> {
+        self.call("close_port")
+            .opened_ports
+            .retain(|opened| opened.port != *port);
+        Ok(())
+    }
+
+    fn get_unit_state(&self) -> Result<HashMap<String, String>> {
+        Ok(self.call("get_unit_state").unit_state.clone())
+    }
+
+    fn set_unit_state(&self, key: &str, value: &str) -> Result<()> {
+        self.call("set_unit_state")
+            .unit_state
+            .insert(key.to_owned(), value.to_owned());
+        Ok(())
+    }
+
+    fn delete_unit_state(&self, key: &str) -> Result<()> {
+        self.call("delete_unit_state").unit_state.remove(key);
+        Ok(())
+    }
+
+    fn resource_path(&self, name: &str) -> Result<PathBuf> {
+        Err(Error::ResourceMissing(name.to_owned()))
+    }
+
+    fn target_base(&self) -> Result<Option<Base>> {
+        Ok(None)
+    }
+
+    fn secret_get(&self, uri: &str) -> Result<HashMap<String, String>> {
+        Err(Error::SecretNotFound(uri.to_owned()))
+    }
+
+    fn related_apps(&self, _endpoint: &str) -> Result<Vec<RelatedApp>> {
+        self.count("related_apps");
+        Ok(vec![])
+    }
+
+    fn related_app(&self, relation_id: &str) -> Result<RelatedApp> {
+        Err(Error::InvalidRelationId(relation_id.to_owned()))
+    }
+
+    fn relation_id(&self) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    fn relation_model_uuid(&self, _app: &RelatedApp) -> Result<String> {
+        self.count("relation_model_uuid");
+        Ok("00000000-0000-0000-0000-000000000000".to_owned())
+    }
+
+    fn relation_get_app(&self, app: &RelatedApp) -> Result<HashMap<String, String>> {
+        Ok(self.databag("relation_get", app.relation_id(), &app.name))
+    }
+
+    fn relation_get_unit(&self, unit: &RelatedUnit) -> Result<HashMap<String, String>> {
+        Ok(self.databag("relation_get", unit.relation_id(), &unit.name))
+    }
+
+    fn relation_get_local_app(&self, app: &RelatedApp) -> Result<HashMap<String, String>> {
+        Ok(self.databag("relation_get", app.relation_id(), "app"))
+    }
+
+    fn relation_get_local_unit(&self, app: &RelatedApp) -> Result<HashMap<String, String>> {
+        Ok(self.databag("relation_get", app.relation_id(), "app/0"))
+    }
+
+    fn relation_set_unit(&self, app: &RelatedApp, key: &str, value: &str) -> Result<()> {
+        self.set_databag("relation_set", app.relation_id(), "app/0", key, value);
+        Ok(())
+    }
+
+    fn relation_set_app(&self, app: &RelatedApp, key: &str, value: &str) -> Result<()> {
+        self.set_databag("relation_set", app.relation_id(), "app", key, value);
+        Ok(())
+    }
+
+    fn relation_set_many(
+        &self,
+        app: &RelatedApp,
+        data: &HashMap<String, Option<String>>,
+        on_app: bool,
+    ) -> Result<()> {
+        let owner = if on_app { "app" } else { "app/0" };
+        for (key, value) in data {
+            self.set_databag(
+                "relation_set",
+                app.relation_id(),
+                owner,
+                key,
+                value.as_deref().unwrap_or_default(),
+            );
+        }
+        Ok(())
+    }
+}
@@ -1,11 +1,16 @@
 use std::{
+    cmp::Ordering,
     collections::HashMap,
     fmt::{Display, Formatter},
+    str::FromStr,
     sync::LazyLock,
 };
 
 use regex::Regex;
 use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::error::Error;
 
 // Error is also a status, but not one that can be directly set.
 pub enum Status<'a> {
@@ -35,6 +40,8 @@ impl Status<'_> {
     }
 }
 
+/// Juju log levels, ordered from least to most severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum LogLevel {
     Debug,
     Info,
@@ -57,6 +64,22 @@ impl Display for LogLevel {
     }
 }
 
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "debug" => Ok(LogLevel::Debug),
+            "info" => Ok(LogLevel::Info),
+            "warning" | "warn" => Ok(LogLevel::Warning),
+            "error" => Ok(LogLevel::Error),
+            _ => Err(format!(
+                "{s:?} is not a valid log level. Expected one of debug, info, warning, or error."
+            )),
+        }
+    }
+}
+
 // ref. https://github.com/canonical/charm-events
 pub enum Event {
     CollectMetrics,
@@ -133,7 +156,517 @@ impl ActionResultKey {
     }
 }
 
-#[derive(Debug, Deserialize)]
+/// Reference to a juju secret, as given by a config option of type `secret`.
+/// Use it as the type of the option in the charm's config struct,
+/// and resolve it to the secret content with `Unit::secret_content`.
+///
+/// ```ignore
+/// #[derive(Deserialize)]
+/// struct Config {
+///     api_token: Option<SecretRef>,
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(try_from = "String")]
+pub struct SecretRef(String);
+
+impl TryFrom<String> for SecretRef {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        // Secret URIs are `secret:<id>`, or `secret://<model-uuid>/<id>` from another model.
+        match value.strip_prefix("secret:") {
+            Some(id) if !id.trim_start_matches('/').is_empty() => Ok(Self(value)),
+            _ => Err(format!(
+                "{value:?} is not a secret URI. Expected a value like \"secret:<id>\"."
+            )),
+        }
+    }
+}
+
+impl SecretRef {
+    pub fn uri(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for SecretRef {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// The oldest juju version supported by the framework.
+/// Features that need a newer version are listed in `JujuFeature`.
+pub const MINIMUM_JUJU_VERSION: JujuVersion = JujuVersion::new(3, 1, 0);
+
+static JUJU_VERSION_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(\d{1,9})\.(\d{1,9})(?:\.|-([a-z]+))(\d{1,9})(?:\.(\d{1,9}))?$")
+        .expect("hardcoded regex in codebase was invalid")
+});
+
+/// A juju version, like `3.6.1`, or `3.1-rc2` for pre-releases.
+/// Pre-releases sort before the release of the same major and minor version.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct JujuVersion {
+    pub major: u32,
+    pub minor: u32,
+    /// Pre-release tag, like `beta` or `rc`.
+    pub tag: Option<String>,
+    pub patch: u32,
+    pub build: u32,
+}
+
+impl JujuVersion {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            tag: None,
+            patch,
+            build: 0,
+        }
+    }
+
+    /// True if this version supports `feature`.
+    pub fn supports(&self, feature: JujuFeature) -> bool {
+        *self >= feature.required_version()
+    }
+}
+
+impl FromStr for JujuVersion {
+    type Err = Error;
+
+    fn from_str(s: &str) -> crate::error::Result<Self> {
+        let captures = JUJU_VERSION_REGEX
+            .captures(s.trim())
+            .ok_or_else(|| Error::InvalidJujuVersion(s.to_owned()))?;
+        // the regex only matches digits here, and limits the length to fit in a u32
+        let number = |i: usize| {
+            captures
+                .get(i)
+                .map_or(0, |m| m.as_str().parse().unwrap_or_default())
+        };
+        Ok(Self {
+            major: number(1),
+            minor: number(2),
+            tag: captures.get(3).map(|m| m.as_str().to_owned()),
+            patch: number(4),
+            build: number(5),
+        })
+    }
+}
+
+impl Display for JujuVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.tag {
+            Some(tag) => write!(f, "{}.{}-{tag}{}", self.major, self.minor, self.patch)?,
+            None => write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?,
+        }
+        if self.build > 0 {
+            write!(f, ".{}", self.build)?;
+        }
+        Ok(())
+    }
+}
+
+impl Ord for JujuVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.major
+            .cmp(&other.major)
+            .then(self.minor.cmp(&other.minor))
+            .then_with(|| match (&self.tag, &other.tag) {
+                (None, None) => Ordering::Equal,
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(tag), Some(other_tag)) => tag.cmp(other_tag),
+            })
+            .then(self.patch.cmp(&other.patch))
+            .then(self.build.cmp(&other.build))
+    }
+}
+
+impl PartialOrd for JujuVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Juju features that are only available from a certain juju version.
+/// Most of these predate `MINIMUM_JUJU_VERSION`;
+/// they are checked so that charms running on an unsupported older juju
+/// (which only logs a warning) get a clear error instead of a failing hook tool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JujuFeature {
+    /// The `secret-*` hook tools.
+    Secrets,
+    /// Server-side unit state with the `state-*` hook tools.
+    UnitState,
+    /// Opening ports for specific endpoints.
+    OpenPortEndpoints,
+    /// The `action-log` hook tool.
+    ActionLog,
+    /// The `pebble-custom-notice` event.
+    PebbleNotices,
+}
+
+impl JujuFeature {
+    /// The oldest juju version supporting this feature.
+    pub fn required_version(&self) -> JujuVersion {
+        match self {
+            // secrets were added in 3.0.0, but the hook tools changed up to 3.0.3
+            JujuFeature::Secrets => JujuVersion::new(3, 0, 3),
+            JujuFeature::UnitState => JujuVersion::new(2, 8, 0),
+            JujuFeature::OpenPortEndpoints => JujuVersion::new(3, 0, 0),
+            JujuFeature::ActionLog => JujuVersion::new(2, 7, 0),
+            JujuFeature::PebbleNotices => JujuVersion::new(3, 4, 0),
+        }
+    }
+}
+
+impl Display for JujuFeature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                JujuFeature::Secrets => "secrets",
+                JujuFeature::UnitState => "unit state",
+                JujuFeature::OpenPortEndpoints => "opening ports per endpoint",
+                JujuFeature::ActionLog => "action logging",
+                JujuFeature::PebbleNotices => "pebble notices",
+            }
+        )
+    }
+}
+
+/// A payload (eg. a container or process) managed by the charm,
+/// to be tracked by juju with the `payload-*` hook tools.
+/// ref. https://juju.is/docs/sdk/metadata-yaml (`payloads`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Payload {
+    /// Name of the payload class, as defined in the charm's metadata.
+    pub class: String,
+    /// Type of the payload, eg. `docker` or `kvm`.
+    pub kind: String,
+    /// Unique id of the payload instance, eg. the container id.
+    pub id: String,
+    /// Extra tags to attach to the payload.
+    pub labels: Vec<String>,
+}
+
+/// Status of a registered payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadStatus {
+    Starting,
+    Running,
+    Stopping,
+    Stopped,
+}
+
+impl Display for PayloadStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                PayloadStatus::Starting => "starting",
+                PayloadStatus::Running => "running",
+                PayloadStatus::Stopping => "stopping",
+                PayloadStatus::Stopped => "stopped",
+            }
+        )
+    }
+}
+
+/// Type of a metric, as defined in the charm's `metrics.yaml`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MetricKind {
+    /// A value measured at a point in time.
+    Gauge,
+    /// A non-negative value counted since the last collection.
+    Absolute,
+}
+
+/// Definition of a metric in the charm's `metrics.yaml`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MetricDefinition {
+    /// Not set for the metrics built in to juju, like `juju-units`.
+    #[serde(rename = "type")]
+    pub kind: Option<MetricKind>,
+    pub description: Option<String>,
+}
+
+/// A metric value to report with `add-metric`.
+/// The variant must match the type of the metric in `metrics.yaml`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MetricValue {
+    Gauge(f64),
+    Absolute(f64),
+}
+
+impl MetricValue {
+    pub fn kind(&self) -> MetricKind {
+        match self {
+            MetricValue::Gauge(_) => MetricKind::Gauge,
+            MetricValue::Absolute(_) => MetricKind::Absolute,
+        }
+    }
+
+    pub fn value(&self) -> f64 {
+        match *self {
+            MetricValue::Gauge(value) | MetricValue::Absolute(value) => value,
+        }
+    }
+}
+
+/// A port or range of ports to open, in the syntax used by the `open-port` hook tool.
+/// Parse from strings like `80`, `80/tcp`, `53/udp`, `8000-8100/tcp`, or `icmp`.
+/// A missing protocol defaults to tcp.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Port {
+    Tcp(u16),
+    Udp(u16),
+    /// Inclusive range of tcp ports.
+    TcpRange(u16, u16),
+    /// Inclusive range of udp ports.
+    UdpRange(u16, u16),
+    Icmp,
+}
+
+impl Port {
+    /// Check the port is valid to pass to the `open-port` and `close-port` hook tools.
+    pub fn validate(&self) -> crate::error::Result<()> {
+        match *self {
+            Port::Tcp(0) | Port::Udp(0) => Err(Error::InvalidPort(format!(
+                "{self}: port numbers must be between 1 and 65535"
+            ))),
+            Port::TcpRange(from, to) | Port::UdpRange(from, to) if from == 0 || from > to => {
+                Err(Error::InvalidPort(format!(
+                    "{self}: ranges must be in the form <from>-<to>, with 1 <= from <= to <= 65535"
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Get the port in the form juju reports it, with single-port ranges
+    /// (eg. `80-80/tcp`) as single ports (`80/tcp`), so ports can be compared with opened ports.
+    pub fn normalized(&self) -> Self {
+        match *self {
+            Port::TcpRange(from, to) if from == to => Port::Tcp(from),
+            Port::UdpRange(from, to) if from == to => Port::Udp(from),
+            port => port,
+        }
+    }
+}
+
+impl FromStr for Port {
+    type Err = Error;
+
+    fn from_str(s: &str) -> crate::error::Result<Self> {
+        let invalid = || {
+            Error::InvalidPort(format!(
+                "{s:?}: expected <port>[/<protocol>], <from>-<to>[/<protocol>], or icmp"
+            ))
+        };
+        if s.eq_ignore_ascii_case("icmp") {
+            return Ok(Port::Icmp);
+        }
+
+        let (ports, protocol) = s.split_once('/').unwrap_or((s, "tcp"));
+        let parse = |port: &str| port.trim().parse::<u16>().map_err(|_| invalid());
+        let port = match (protocol.to_lowercase().as_str(), ports.split_once('-')) {
+            ("tcp", None) => Port::Tcp(parse(ports)?),
+            ("udp", None) => Port::Udp(parse(ports)?),
+            ("tcp", Some((from, to))) => Port::TcpRange(parse(from)?, parse(to)?),
+            ("udp", Some((from, to))) => Port::UdpRange(parse(from)?, parse(to)?),
+            _ => return Err(invalid()),
+        };
+        port.validate()?;
+        Ok(port.normalized())
+    }
+}
+
+impl Display for Port {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Port::Tcp(port) => write!(f, "{port}/tcp"),
+            Port::Udp(port) => write!(f, "{port}/udp"),
+            Port::TcpRange(from, to) => write!(f, "{from}-{to}/tcp"),
+            Port::UdpRange(from, to) => write!(f, "{from}-{to}/udp"),
+            Port::Icmp => write!(f, "icmp"),
+        }
+    }
+}
+
+/// A port opened by this unit, as reported by the `opened-ports` hook tool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenedPort {
+    pub port: Port,
+    /// Endpoints the port is opened for.
+    /// Empty if the port is opened for all endpoints.
+    pub endpoints: Vec<String>,
+}
+
+impl FromStr for OpenedPort {
+    type Err = Error;
+
+    /// Parse a line of `opened-ports --endpoints` output, like `80/tcp (*)` or
+    /// `8080/tcp (website, admin)`.
+    fn from_str(s: &str) -> crate::error::Result<Self> {
+        let (port, endpoints) = match s.split_once('(') {
+            Some((port, endpoints)) => (port, endpoints.trim_end().trim_end_matches(')')),
+            None => (s, "*"),
+        };
+        Ok(Self {
+            port: port.trim().parse()?,
+            endpoints: endpoints
+                .split(',')
+                .map(str::trim)
+                .filter(|endpoint| !endpoint.is_empty() && *endpoint != "*")
+                .map(str::to_owned)
+                .collect(),
+        })
+    }
+}
+
+/// Details of an OCI image resource, for pulling the image from its registry.
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+pub struct OciImage {
+    /// The full image path, eg. `registry.jujucharms.com/charm/abc/myimage@sha256:...`.
+    #[serde(rename = "registrypath")]
+    pub registry_path: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl std::fmt::Debug for OciImage {
+    // Keep the registry password out of logs.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OciImage")
+            .field("registry_path", &self.registry_path)
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+/// An OS base a machine can run, like `ubuntu@22.04`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Base {
+    /// The OS name, eg. `ubuntu`.
+    pub name: String,
+    /// The OS version, eg. `22.04`.
+    pub channel: String,
+}
+
+impl Base {
+    /// Get the ubuntu base for a series name, as used by juju 2.9 (eg. `jammy`).
+    pub fn from_series(series: &str) -> crate::error::Result<Self> {
+        let channel = match series {
+            "xenial" => "16.04",
+            "bionic" => "18.04",
+            "focal" => "20.04",
+            "jammy" => "22.04",
+            "noble" => "24.04",
+            _ => return Err(Error::InvalidBase(series.to_owned())),
+        };
+        Ok(Self {
+            name: "ubuntu".to_owned(),
+            channel: channel.to_owned(),
+        })
+    }
+}
+
+impl FromStr for Base {
+    type Err = Error;
+
+    /// Parse a base in the format `name@channel`, like `ubuntu@22.04`.
+    fn from_str(s: &str) -> crate::error::Result<Self> {
+        match s.trim().split_once('@') {
+            Some((name, channel)) if !name.is_empty() && !channel.is_empty() => Ok(Self {
+                name: name.to_owned(),
+                channel: channel.to_owned(),
+            }),
+            _ => Err(Error::InvalidBase(s.to_owned())),
+        }
+    }
+}
+
+impl Display for Base {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}@{}", self.name, self.channel)
+    }
+}
+
+/// Old and new values of a config option that has changed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigChange {
+    pub old: Value,
+    pub new: Value,
+}
+
+/// Differences between two snapshots of the charm config, keyed by option name.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ConfigDiff {
+    /// Options that were not set previously, with their new values.
+    pub added: HashMap<String, Value>,
+    /// Options that are no longer set, with their old values.
+    pub removed: HashMap<String, Value>,
+    /// Options that have a different value.
+    pub changed: HashMap<String, ConfigChange>,
+}
+
+impl ConfigDiff {
+    pub fn between(old: &Map<String, Value>, new: &Map<String, Value>) -> Self {
+        let mut diff = Self::default();
+        for (name, new_value) in new {
+            match old.get(name) {
+                None => {
+                    diff.added.insert(name.clone(), new_value.clone());
+                }
+                Some(old_value) if old_value != new_value => {
+                    diff.changed.insert(
+                        name.clone(),
+                        ConfigChange {
+                            old: old_value.clone(),
+                            new: new_value.clone(),
+                        },
+                    );
+                }
+                Some(_) => {}
+            }
+        }
+        for (name, old_value) in old {
+            if !new.contains_key(name) {
+                diff.removed.insert(name.clone(), old_value.clone());
+            }
+        }
+        diff
+    }
+
+    /// True if nothing changed.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// True if the option `name` was added, removed, or changed.
+    pub fn contains(&self, name: &str) -> bool {
+        self.added.contains_key(name)
+            || self.removed.contains_key(name)
+            || self.changed.contains_key(name)
+    }
+
+    /// True if any of the options in `names` were added, removed, or changed.
+    pub fn contains_any(&self, names: &[&str]) -> bool {
+        names.iter().any(|name| self.contains(name))
+    }
+}
+
+/// Attributes for the `certificate` auth type (eg. LXD).
+#[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all(deserialize = "kebab-case"))]
 pub struct JujuCredentialsCredentialAttrs {
     pub client_cert: String,
@@ -141,22 +674,136 @@ pub struct JujuCredentialsCredentialAttrs {
     pub server_cert: String,
 }
 
-#[derive(Debug, Deserialize)]
+/// Attributes for the `userpass` auth type (eg. OpenStack, vSphere, MAAS).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all(deserialize = "kebab-case"))]
+pub struct UserPassCredentialAttrs {
+    /// vSphere calls this `user`.
+    #[serde(alias = "user")]
+    pub username: String,
+    pub password: String,
+    /// Cloud specific attributes, like `tenant-name` or `domain-name` for OpenStack.
+    #[serde(flatten)]
+    pub extra: HashMap<String, String>,
+}
+
+/// Attributes for the `access-key` auth type (eg. AWS, OpenStack).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all(deserialize = "kebab-case"))]
+pub struct AccessKeyCredentialAttrs {
+    pub access_key: String,
+    pub secret_key: String,
+    /// Cloud specific attributes, like `tenant-name` for OpenStack.
+    #[serde(flatten)]
+    pub extra: HashMap<String, String>,
+}
+
+/// Attributes for the `oauth1` auth type (eg. MAAS).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all(deserialize = "kebab-case"))]
+pub struct OAuth1CredentialAttrs {
+    pub maas_oauth: String,
+}
+
+/// Attributes for the `oauth2` auth type (eg. GCE).
+#[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all(deserialize = "kebab-case"))]
-pub struct JujuCredentialsCredential {
-    pub auth_type: String,
-    pub attrs: JujuCredentialsCredentialAttrs,
+pub struct OAuth2CredentialAttrs {
+    pub client_id: String,
+    pub client_email: String,
+    pub private_key: String,
+    pub project_id: String,
+}
+
+/// A cloud credential, with attributes depending on the `auth-type`.
+#[derive(Debug, Clone)]
+pub enum JujuCredentialsCredential {
+    Certificate(JujuCredentialsCredentialAttrs),
+    UserPass(UserPassCredentialAttrs),
+    AccessKey(AccessKeyCredentialAttrs),
+    OAuth1(OAuth1CredentialAttrs),
+    OAuth2(OAuth2CredentialAttrs),
+    /// Any other auth type, or a known auth type with attributes other than expected,
+    /// with the raw attributes.
+    Other {
+        auth_type: String,
+        attrs: HashMap<String, String>,
+    },
+}
+
+impl JujuCredentialsCredential {
+    /// The auth type as given by juju, eg. `userpass`.
+    pub fn auth_type(&self) -> &str {
+        match self {
+            JujuCredentialsCredential::Certificate(_) => "certificate",
+            JujuCredentialsCredential::UserPass(_) => "userpass",
+            JujuCredentialsCredential::AccessKey(_) => "access-key",
+            JujuCredentialsCredential::OAuth1(_) => "oauth1",
+            JujuCredentialsCredential::OAuth2(_) => "oauth2",
+            JujuCredentialsCredential::Other { auth_type, .. } => auth_type,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for JujuCredentialsCredential {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(rename_all(deserialize = "kebab-case"))]
+        struct RawCredential {
+            auth_type: String,
+            #[serde(default)]
+            attrs: HashMap<String, String>,
+        }
+
+        fn typed<T: serde::de::DeserializeOwned>(attrs: &HashMap<String, String>) -> Option<T> {
+            serde_json::to_value(attrs)
+                .and_then(serde_json::from_value)
+                .ok()
+        }
+
+        let raw = RawCredential::deserialize(deserializer)?;
+        // Clouds reuse auth type names with different attributes
+        // (eg. kubernetes `oauth2` credentials only have a `Token`),
+        // so keep the raw attributes if they don't match the expected ones.
+        let credential = match raw.auth_type.as_str() {
+            "certificate" => typed(&raw.attrs).map(JujuCredentialsCredential::Certificate),
+            "userpass" => typed(&raw.attrs).map(JujuCredentialsCredential::UserPass),
+            "access-key" => typed(&raw.attrs).map(JujuCredentialsCredential::AccessKey),
+            "oauth1" => typed(&raw.attrs).map(JujuCredentialsCredential::OAuth1),
+            "oauth2" => typed(&raw.attrs).map(JujuCredentialsCredential::OAuth2),
+            _ => None,
+        };
+        Ok(credential.unwrap_or(JujuCredentialsCredential::Other {
+            auth_type: raw.auth_type,
+            attrs: raw.attrs,
+        }))
+    }
 }
 
-#[derive(Debug, Deserialize)]
+/// The cloud the model is deployed on, and the credential for it,
+/// as returned by the `credential-get` hook tool.
+#[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all(deserialize = "kebab-case"))]
 pub struct JujuCredentials {
     #[serde(rename = "type")]
     pub cloud_type: String,
     pub name: String,
-    pub region: String,
-    pub endpoint: String,
+    /// The region the model is deployed in.
+    /// `credential-get` only reports the model's region, not the cloud's list of regions.
+    pub region: Option<String>,
+    pub endpoint: Option<String>,
+    pub identity_endpoint: Option<String>,
+    pub storage_endpoint: Option<String>,
     pub credential: JujuCredentialsCredential,
+    /// CA certificates to trust when connecting to the cloud endpoints.
+    #[serde(default, rename = "cacertificates")]
+    pub ca_certificates: Vec<String>,
+    #[serde(default)]
+    pub skip_tls_verify: bool,
+    #[serde(default)]
     pub is_controller_cloud: bool,
 }
 
@@ -169,6 +816,7 @@ pub struct JujuCredentials {
 // --> these are the unit names
 // note: there could be an application name, but no unit names,
 // if the application is related but not finished installing.
+#[derive(Debug, Clone)]
 pub struct RelatedApp {
     /// Name of the deployed application (not the charm name).
     pub name: String,
@@ -181,6 +829,15 @@ pub struct RelatedApp {
     pub(crate) relation_id: String,
 }
 
+impl RelatedApp {
+    /// Unique ID for this relation (eg. `database:3`).
+    /// It should be unique across the juju model.
+    pub fn relation_id(&self) -> &str {
+        &self.relation_id
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct RelatedUnit {
     /// Name of the unit
     pub name: String,
@@ -191,3 +848,251 @@ pub struct RelatedUnit {
     /// Unique ID for this relation. It should be unique across the juju model.
     pub(crate) relation_id: String,
 }
+
+impl RelatedUnit {
+    /// Unique ID for the relation this unit is related by (eg. `database:3`).
+    /// It should be unique across the juju model.
+    pub fn relation_id(&self) -> &str {
+        &self.relation_id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn object(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(map) => map,
+            _ => panic!("expected a json object"),
+        }
+    }
+
+    #[test]
+    fn config_diff_between() {
+        let old = object(json!({"region": "a", "port": 80, "debug": true}));
+        let new = object(json!({"region": "b", "port": 80, "name": "x"}));
+        let diff = ConfigDiff::between(&old, &new);
+
+        assert_eq!(diff.added, HashMap::from([("name".to_owned(), json!("x"))]));
+        assert_eq!(
+            diff.removed,
+            HashMap::from([("debug".to_owned(), json!(true))])
+        );
+        assert_eq!(
+            diff.changed,
+            HashMap::from([(
+                "region".to_owned(),
+                ConfigChange {
+                    old: json!("a"),
+                    new: json!("b"),
+                },
+            )])
+        );
+        assert!(!diff.contains("port"));
+        assert!(diff.contains_any(&["port", "debug"]));
+    }
+
+    #[test]
+    fn config_diff_unchanged_is_empty() {
+        let config = object(json!({"region": "a"}));
+        assert!(ConfigDiff::between(&config, &config).is_empty());
+        assert!(ConfigDiff::between(&Map::new(), &Map::new()).is_empty());
+    }
+
+    #[test]
+    fn parse_ports() {
+        assert_eq!("80".parse::<Port>().unwrap(), Port::Tcp(80));
+        assert_eq!("53/UDP".parse::<Port>().unwrap(), Port::Udp(53));
+        assert_eq!(
+            "8000-8100/tcp".parse::<Port>().unwrap(),
+            Port::TcpRange(8000, 8100)
+        );
+        assert_eq!("icmp".parse::<Port>().unwrap(), Port::Icmp);
+        // single-port ranges are reported by juju as single ports
+        assert_eq!("80-80/udp".parse::<Port>().unwrap(), Port::Udp(80));
+        assert_eq!(Port::TcpRange(80, 80).normalized(), Port::Tcp(80));
+    }
+
+    #[test]
+    fn parse_invalid_ports() {
+        for port in ["0", "65536", "80/sctp", "90-80", "0-10/udp", "http", ""] {
+            assert!(port.parse::<Port>().is_err(), "{port:?} should be invalid");
+        }
+    }
+
+    #[test]
+    fn port_display_round_trips() {
+        for port in [
+            Port::Tcp(80),
+            Port::Udp(53),
+            Port::TcpRange(1, 2),
+            Port::UdpRange(1000, 2000),
+            Port::Icmp,
+        ] {
+            assert_eq!(port.to_string().parse::<Port>().unwrap(), port);
+        }
+    }
+
+    #[test]
+    fn parse_opened_ports() {
+        assert_eq!(
+            "80/tcp (*)".parse::<OpenedPort>().unwrap(),
+            OpenedPort {
+                port: Port::Tcp(80),
+                endpoints: vec![],
+            }
+        );
+        assert_eq!(
+            "8000-8100/udp (website, admin)"
+                .parse::<OpenedPort>()
+                .unwrap(),
+            OpenedPort {
+                port: Port::UdpRange(8000, 8100),
+                endpoints: vec!["website".to_owned(), "admin".to_owned()],
+            }
+        );
+        // without --endpoints
+        assert_eq!(
+            "icmp".parse::<OpenedPort>().unwrap(),
+            OpenedPort {
+                port: Port::Icmp,
+                endpoints: vec![],
+            }
+        );
+    }
+
+    fn version(s: &str) -> JujuVersion {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_juju_versions() {
+        assert_eq!(version("3.6.1"), JujuVersion::new(3, 6, 1));
+        assert_eq!(
+            version("2.9.45.1"),
+            JujuVersion {
+                build: 1,
+                ..JujuVersion::new(2, 9, 45)
+            }
+        );
+        assert_eq!(
+            version("3.1-rc2"),
+            JujuVersion {
+                tag: Some("rc".to_owned()),
+                ..JujuVersion::new(3, 1, 2)
+            }
+        );
+        for invalid in ["", "3", "3.1", "3.1.x", "v3.1.0", "3.1-RC2"] {
+            assert!(invalid.parse::<JujuVersion>().is_err(), "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn juju_version_display_round_trips() {
+        for s in ["3.6.1", "2.9.45.1", "3.1-rc2", "3.4-beta1.2"] {
+            assert_eq!(version(s).to_string(), s);
+        }
+    }
+
+    #[test]
+    fn juju_version_ordering() {
+        let ordered = [
+            "2.9.45",
+            "2.9.45.1",
+            "3.0.3",
+            "3.1-beta1",
+            "3.1-rc1",
+            "3.1-rc2",
+            "3.1.0",
+            "3.1.10",
+            "3.10.0",
+        ];
+        for pair in ordered.windows(2) {
+            assert!(version(pair[0]) < version(pair[1]), "{pair:?}");
+        }
+    }
+
+    #[test]
+    fn juju_version_supports_features() {
+        assert!(version("3.0.3").supports(JujuFeature::Secrets));
+        assert!(!version("3.0.2").supports(JujuFeature::Secrets));
+        assert!(!version("3.0-rc3").supports(JujuFeature::OpenPortEndpoints));
+        assert!(MINIMUM_JUJU_VERSION.supports(JujuFeature::Secrets));
+        assert!(!MINIMUM_JUJU_VERSION.supports(JujuFeature::PebbleNotices));
+        assert!(version("3.4.0").supports(JujuFeature::PebbleNotices));
+    }
+
+    fn credential(value: Value) -> JujuCredentialsCredential {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn userpass_credential() {
+        let JujuCredentialsCredential::UserPass(attrs) = credential(json!({
+            "auth-type": "userpass",
+            "attrs": {"username": "admin", "password": "pw", "tenant-name": "demo"},
+        })) else {
+            panic!("expected a userpass credential");
+        };
+        assert_eq!(attrs.username, "admin");
+        assert_eq!(attrs.password, "pw");
+        assert_eq!(
+            attrs.extra,
+            HashMap::from([("tenant-name".to_owned(), "demo".to_owned())])
+        );
+
+        // vsphere
+        let JujuCredentialsCredential::UserPass(attrs) = credential(json!({
+            "auth-type": "userpass",
+            "attrs": {"user": "administrator", "password": "pw", "vmfolder": "juju"},
+        })) else {
+            panic!("expected a userpass credential");
+        };
+        assert_eq!(attrs.username, "administrator");
+    }
+
+    #[test]
+    fn access_key_credential() {
+        let credential = credential(json!({
+            "auth-type": "access-key",
+            "attrs": {"access-key": "AKIA", "secret-key": "secret"},
+        }));
+        assert_eq!(credential.auth_type(), "access-key");
+        let JujuCredentialsCredential::AccessKey(attrs) = credential else {
+            panic!("expected an access-key credential");
+        };
+        assert_eq!(attrs.access_key, "AKIA");
+        assert_eq!(attrs.secret_key, "secret");
+        assert!(attrs.extra.is_empty());
+    }
+
+    #[test]
+    fn unknown_auth_type_keeps_raw_attrs() {
+        let credential = credential(json!({
+            "auth-type": "instance-role",
+            "attrs": {"instance-profile-name": "juju"},
+        }));
+        assert_eq!(credential.auth_type(), "instance-role");
+        assert!(matches!(
+            credential,
+            JujuCredentialsCredential::Other { attrs, .. }
+                if attrs == HashMap::from([("instance-profile-name".to_owned(), "juju".to_owned())])
+        ));
+    }
+
+    #[test]
+    fn mismatched_attrs_fall_back_to_other() {
+        // kubernetes uses oauth2 with only a token
+        let credential = credential(json!({
+            "auth-type": "oauth2",
+            "attrs": {"Token": "abc"},
+        }));
+        assert_eq!(credential.auth_type(), "oauth2");
+        assert!(matches!(
+            credential,
+            JujuCredentialsCredential::Other { attrs, .. } if attrs["Token"] == "abc"
+        ));
+    }
+}